@@ -15,6 +15,8 @@ pub struct App {
     notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
     lobby: Option<Lobby>,
     selfid: String,
+    /// Why the server ended the session, if it did
+    closed: Option<CloseCodes>,
     link: ComponentLink<Self>,
     ping_interval: yew::services::interval::IntervalTask,
 }
@@ -24,8 +26,15 @@ pub enum Msg {
     Ping,
     LobbyJoined(String, Lobby),
     GameStart(Lobby),
+    Refresh(Lobby),
 
     Disconnected,
+    Reconnecting(u32),
+    Reconnected,
+    Closed(CloseCodes),
+    Reload,
+    TakeOver,
+    Leave,
     PlayerDisconnected(Player),
     PlayerJoined(Player),
 }
@@ -53,9 +62,13 @@ impl Component for App {
             AgentOutput::SocketMessage(msg) => match msg {
                 SocketMessage::PlayerJoined(p) => Msg::PlayerJoined(p),
                 SocketMessage::PlayerDisconnected(p) => Msg::PlayerDisconnected(p),
+                SocketMessage::LobbyJoined(lobby) => Msg::Refresh(lobby),
+                SocketMessage::Close(code) => Msg::Closed(code),
                 _ => Msg::Ignore,
             },
             AgentOutput::SocketDisconnected => Msg::Disconnected,
+            AgentOutput::SocketReconnecting(attempt) => Msg::Reconnecting(attempt),
+            AgentOutput::SocketReconnected => Msg::Reconnected,
             _ => Msg::Ignore,
         }));
         let pinginterval = yew::services::IntervalService::spawn(
@@ -68,6 +81,7 @@ impl Component for App {
             lobby: None,
             link: _link,
             selfid: unsafe { crate::home::get_uid() },
+            closed: None,
             ping_interval: pinginterval,
         }
     }
@@ -90,7 +104,14 @@ impl Component for App {
                 self.lobby = Some(lob);
                 true
             }
+            Msg::Refresh(lob) => {
+                self.lobby = Some(lob);
+                self.closed = None;
+                true
+            }
 
+            // The close screen or the notice on the home page already says why
+            Msg::Disconnected if self.closed.is_some() => false,
             Msg::Disconnected => {
                 self.notif_agent
                     .send(NotificationAgentInput::Notify(Notification {
@@ -99,6 +120,46 @@ impl Component for App {
                     }));
                 false
             }
+            Msg::Reconnecting(attempt) => {
+                // Only once, a bad connection would fill the screen with toasts
+                if attempt == 1 {
+                    self.notif_agent
+                        .send(NotificationAgentInput::Notify(Notification {
+                            notification_type: NotificationType::Warning,
+                            content: "Connection lost, reconnecting".to_string(),
+                        }));
+                }
+                false
+            }
+            Msg::Reload => {
+                if let Err(err) = yew::utils::window().location().reload() {
+                    log::warn!("Cant reload {:#?}", err);
+                }
+                false
+            }
+            Msg::Closed(code) => {
+                self.closed = Some(code);
+                true
+            }
+            Msg::TakeOver => {
+                self.closed = None;
+                self._agent.send(AgentInput::Resume);
+                true
+            }
+            Msg::Leave => {
+                self.closed = None;
+                self.lobby = None;
+                go_to_route(Route::from(AppRoute::Home));
+                true
+            }
+            Msg::Reconnected => {
+                self.notif_agent
+                    .send(NotificationAgentInput::Notify(Notification {
+                        notification_type: NotificationType::Info,
+                        content: "Reconnected".to_string(),
+                    }));
+                false
+            }
             Msg::PlayerJoined(p) => {
                 self.notif_agent
                     .send(NotificationAgentInput::Notify(Notification {
@@ -123,6 +184,15 @@ impl Component for App {
     }
 
     fn view(&self) -> Html {
+        match self.closed {
+            // Home shows why joining failed, there is no game to cover yet
+            Some(code)
+                if self.lobby.is_some() || matches!(code, CloseCodes::IncompatibleVersion(_)) =>
+            {
+                return self.closed_screen(code)
+            }
+            _ => {}
+        }
         let home = html! {
             <Home prefillroomid="".to_string() lobbyjoinedcb=self.link.callback(move |f:(String,Lobby)|Msg::LobbyJoined(f.0,f.1))/>
         };
@@ -167,3 +237,52 @@ impl Component for App {
         }
     }
 }
+
+impl App {
+    /// Covers the game once the server ended the session, with what the
+    /// player can do next
+    fn closed_screen(&self, code: CloseCodes) -> Html {
+        let (subtitle, actions) = match code {
+            CloseCodes::IncompatibleVersion(_) => (
+                "Please refresh to keep playing",
+                html! {
+                    <button class="button is-primary" onclick=self.link.callback(|_|Msg::Reload)>{"Refresh"}</button>
+                },
+            ),
+            CloseCodes::NewSessionOpened => (
+                "Play here instead? The other tab will be disconnected",
+                html! {
+                    <>
+                    <button class="button is-primary" onclick=self.link.callback(|_|Msg::TakeOver)>{"Take over"}</button>
+                    <button class="button" onclick=self.link.callback(|_|Msg::Leave)>{"Leave"}</button>
+                    </>
+                },
+            ),
+            CloseCodes::CantLoinLobbyDoestExist => (
+                "The room closed while you were away",
+                html! {
+                    <button class="button is-primary" onclick=self.link.callback(|_|Msg::Leave)>{"Back to start"}</button>
+                },
+            ),
+            _ => (
+                "You can start or join another game",
+                html! {
+                    <button class="button is-primary" onclick=self.link.callback(|_|Msg::Leave)>{"Back to start"}</button>
+                },
+            ),
+        };
+        html! {
+            <section class="hero is-fullheight">
+                <div class="hero-body">
+                    <div class="container has-text-centered">
+                        <h1 class="title">{code.to_string()}</h1>
+                        <h2 class="subtitle">{subtitle}</h2>
+                        <div class="buttons is-centered">
+                            {actions}
+                        </div>
+                    </div>
+                </div>
+            </section>
+        }
+    }
+}
@@ -0,0 +1,535 @@
+//! Compact wire format for strokes.
+//!
+//! bincode writes every coordinate as an f64 and every colour
+//! as a string, which adds up quickly for a drawing streamed every 100ms.
+//! This format instead writes a small header per stroke and the points as
+//! varint deltas of integer coordinates on the board.
+//!
+//! ```text
+//! batch  := version:u8 varint(count) stroke*
+//! stroke := varint(id) tool:u8 flags:u8 color
+//!           [opacity:u8]                      with HAS_OPACITY
+//!           varint(line_width) varint(count) point*
+//! color  := palette index:u8 | CUSTOM_COLOR r:u8 g:u8 b:u8
+//! point  := zigzag(dx) zigzag(dy) zigzag(dt)  from the previous point, or 0,0,0
+//!           [pressure:u8]                     with HAS_PRESSURE
+//! ```
+//!
+//! Coordinates are rounded to `1 / COORD_STEPS` of a board unit, decoding
+//! and encoding again gives the same bytes.
+
+use std::convert::TryFrom;
+
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+use crate::structures::*;
+
+/// Bumped whenever the layout below changes
+const VERSION: u8 = 6;
+
+/// Coordinates are stored in steps of `1 / COORD_STEPS` board units
+const COORD_STEPS: f64 = 4_f64;
+
+/// Marks a colour that is not in `PALETTE` and follows as rgb bytes
+const CUSTOM_COLOR: u8 = u8::MAX;
+
+/// Stroke flag for points carrying their own pressure, the rest draw at 1
+const HAS_PRESSURE: u8 = 1;
+
+/// Stroke flag for an opacity byte after the colour, the rest are opaque
+const HAS_OPACITY: u8 = 2;
+
+/// Pressure is stored in steps of `1 / PRESSURE_STEPS`, up to almost 4
+const PRESSURE_STEPS: f64 = 64_f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    UnsupportedVersion(u8),
+    UnknownTool(u8),
+    UnknownColor(u8),
+    VarintOverflow,
+    /// A value past the range of its field, or coordinates adding up past it
+    Overflow,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+pub fn encode(strokes: &[Stroke]) -> Vec<u8> {
+    let mut out = vec![VERSION];
+    write_varint(&mut out, strokes.len() as u64);
+    for stroke in strokes {
+        encode_stroke(&mut out, stroke);
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Stroke>, DecodeError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let count = reader.varint()?;
+    let mut strokes = vec![];
+    for _ in 0..count {
+        strokes.push(decode_stroke(&mut reader)?);
+    }
+    Ok(strokes)
+}
+
+/// For `#[serde(with = "crate::codec")]` on `Vec<Stroke>` fields
+pub fn serialize<S: Serializer>(strokes: &[Stroke], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(&encode(strokes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Stroke>, D::Error> {
+    let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+    decode(&bytes).map_err(D::Error::custom)
+}
+
+/// For `#[serde(with = "crate::codec::single")]` on `Stroke` fields
+pub mod single {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(stroke: &Stroke, serializer: S) -> Result<S::Ok, S::Error> {
+        super::serialize(std::slice::from_ref(stroke), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Stroke, D::Error> {
+        let mut strokes = super::deserialize(deserializer)?;
+        match (strokes.pop(), strokes.is_empty()) {
+            (Some(stroke), true) => Ok(stroke),
+            _ => Err(D::Error::custom("expected exactly one stroke")),
+        }
+    }
+}
+
+fn encode_stroke(out: &mut Vec<u8>, stroke: &Stroke) {
+    let header = &stroke.header;
+    let has_pressure = stroke
+        .points
+        .iter()
+        .any(|point| (point.pressure - 1_f64).abs() > f64::EPSILON);
+    let has_opacity = header.opacity < u8::MAX;
+    write_varint(out, stroke.id as u64);
+    out.push(tool_index(header.tool));
+    let mut flags = 0;
+    if has_pressure {
+        flags |= HAS_PRESSURE;
+    }
+    if has_opacity {
+        flags |= HAS_OPACITY;
+    }
+    out.push(flags);
+    match PALETTE.iter().position(|color| color == &header.color) {
+        Some(index) => out.push(index as u8),
+        None => {
+            let Color { r, g, b } = header.color;
+            out.extend_from_slice(&[CUSTOM_COLOR, r, g, b]);
+        }
+    }
+    if has_opacity {
+        out.push(header.opacity);
+    }
+    write_varint(out, header.line_width as u64);
+
+    write_varint(out, stroke.points.len() as u64);
+    let (mut lx, mut ly, mut lt) = (0, 0, 0);
+    for point in stroke.points.iter() {
+        let x = quantize(point.x);
+        let y = quantize(point.y);
+        let t = point.time as i64;
+        write_varint(out, zigzag(x - lx));
+        write_varint(out, zigzag(y - ly));
+        write_varint(out, zigzag(t - lt));
+        if has_pressure {
+            out.push(
+                (point.pressure * PRESSURE_STEPS)
+                    .round()
+                    .clamp(0_f64, 255_f64) as u8,
+            );
+        }
+        lx = x;
+        ly = y;
+        lt = t;
+    }
+}
+
+fn decode_stroke(reader: &mut Reader) -> Result<Stroke, DecodeError> {
+    let id = u32::try_from(reader.varint()?).map_err(|_| DecodeError::Overflow)?;
+    let tool = tool_from_index(reader.byte()?)?;
+    let flags = reader.byte()?;
+    let color = match reader.byte()? {
+        CUSTOM_COLOR => Color::rgb(reader.byte()?, reader.byte()?, reader.byte()?),
+        index => *PALETTE
+            .get(index as usize)
+            .ok_or(DecodeError::UnknownColor(index))?,
+    };
+    let opacity = if flags & HAS_OPACITY != 0 {
+        reader.byte()?
+    } else {
+        u8::MAX
+    };
+    let line_width = u32::try_from(reader.varint()?).map_err(|_| DecodeError::Overflow)?;
+
+    let count = reader.varint()?;
+    let mut points = vec![];
+    let (mut x, mut y, mut t) = (0, 0, 0);
+    for _ in 0..count {
+        x = add_delta(x, reader.varint()?)?;
+        y = add_delta(y, reader.varint()?)?;
+        t = add_delta(t, reader.varint()?)?;
+        let pressure = if flags & HAS_PRESSURE != 0 {
+            reader.byte()? as f64 / PRESSURE_STEPS
+        } else {
+            1_f64
+        };
+        points.push(Point {
+            x: x as f64 / COORD_STEPS,
+            y: y as f64 / COORD_STEPS,
+            pressure,
+            time: u32::try_from(t).map_err(|_| DecodeError::Overflow)?,
+        });
+    }
+    Ok(Stroke {
+        id,
+        header: StrokeHeader {
+            tool,
+            color,
+            line_width,
+            opacity,
+        },
+        points,
+    })
+}
+
+/// Points dragged off the board can be negative or past the edge,
+/// the signed deltas cover that
+fn quantize(value: f64) -> i64 {
+    (value * COORD_STEPS).round() as i64
+}
+
+fn tool_index(tool: Tool) -> u8 {
+    match tool {
+        Tool::Brush => 0,
+        Tool::Eraser => 1,
+        Tool::Fill => 2,
+        Tool::Line => 3,
+        Tool::Rectangle => 4,
+        Tool::Ellipse => 5,
+        Tool::Highlighter => 6,
+    }
+}
+
+fn tool_from_index(index: u8) -> Result<Tool, DecodeError> {
+    Ok(match index {
+        0 => Tool::Brush,
+        1 => Tool::Eraser,
+        2 => Tool::Fill,
+        3 => Tool::Line,
+        4 => Tool::Rectangle,
+        5 => Tool::Ellipse,
+        6 => Tool::Highlighter,
+        _ => return Err(DecodeError::UnknownTool(index)),
+    })
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn add_delta(value: i64, delta: u64) -> Result<i64, DecodeError> {
+    value
+        .checked_add(unzigzag(delta))
+        .ok_or(DecodeError::Overflow)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self.bytes.get(self.pos).ok_or(DecodeError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::VarintOverflow)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "encoded strokes")
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = vec![];
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    /// The per point layout `AddPoints` used before strokes existed
+    #[derive(Serialize)]
+    struct LegacyPoint {
+        id: u32,
+        line_width: u32,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        draw: bool,
+        color: String,
+        eraser: bool,
+    }
+
+    const CUSTOM: Color = Color::rgb(0x12, 0xab, 0x34);
+
+    /// A wavy brush stroke with coordinates between the wire's steps
+    fn stroke(id: u32, color: Color, points: usize) -> Stroke {
+        let wave: Vec<_> = (0..points)
+            .map(|i| {
+                let i = i as f64;
+                (
+                    100.25 + (i * 0.7).sin() * 40_f64 + i * 1.3,
+                    200.5 + (i * 0.4).cos() * 30_f64,
+                    1_f64,
+                )
+            })
+            .collect();
+        Stroke::fixture(id, Tool::Brush, color, &wave)
+    }
+
+    fn legacy(strokes: &[Stroke]) -> Vec<LegacyPoint> {
+        let mut id = 0;
+        let mut points = vec![];
+        for stroke in strokes {
+            for (i, point) in stroke.points.iter().enumerate() {
+                points.push(LegacyPoint {
+                    id,
+                    line_width: stroke.header.line_width,
+                    x: point.x,
+                    y: point.y,
+                    width: BOARD_WIDTH,
+                    height: BOARD_HEIGHT,
+                    draw: i > 0,
+                    color: stroke.header.color.to_string(),
+                    eraser: stroke.header.tool == Tool::Eraser,
+                });
+                id += 1;
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn round_trip_is_lossless_at_logical_resolution() {
+        let strokes = vec![stroke(0, PALETTE[1], 50), stroke(1, CUSTOM, 3)];
+        let decoded = decode(&encode(&strokes)).unwrap();
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].header.color, CUSTOM);
+        for (original, decoded) in strokes.iter().zip(decoded.iter()) {
+            assert_eq!(original.id, decoded.id);
+            assert_eq!(original.header.tool, decoded.header.tool);
+            assert_eq!(original.points.len(), decoded.points.len());
+            for (a, b) in original.points.iter().zip(decoded.points.iter()) {
+                assert!((a.x - b.x).abs() < 0.5, "{} {}", a.x, b.x);
+                assert!((a.y - b.y).abs() < 0.5, "{} {}", a.y, b.y);
+                assert_eq!(a.time, b.time);
+            }
+        }
+        assert_eq!(decode(&encode(&decoded)).unwrap(), decoded);
+    }
+
+    #[test]
+    fn points_off_the_canvas_survive() {
+        let mut strokes = vec![stroke(7, Color::BLACK, 2)];
+        strokes[0].points[0] = Point {
+            x: -20_f64,
+            y: 2000_f64,
+            pressure: 1_f64,
+            time: 0,
+        };
+        let decoded = decode(&encode(&strokes)).unwrap();
+        assert!((decoded[0].points[0].x + 20_f64).abs() < 0.5);
+        assert!((decoded[0].points[0].y - 2000_f64).abs() < 0.5);
+    }
+
+    #[test]
+    fn pressure_only_costs_bytes_when_used() {
+        let flat = stroke(1, Color::BLACK, 20);
+        let mut pen = flat.clone();
+        for (i, point) in pen.points.iter_mut().enumerate() {
+            point.pressure = 0.2 + i as f64 * 0.1;
+        }
+        let flat_len = encode(&[flat]).len();
+        let pen_bytes = encode(&[pen.clone()]);
+        assert_eq!(pen_bytes.len(), flat_len + 20);
+
+        let decoded = decode(&pen_bytes).unwrap();
+        for (a, b) in pen.points.iter().zip(decoded[0].points.iter()) {
+            assert!((a.pressure - b.pressure).abs() <= 0.5 / PRESSURE_STEPS);
+        }
+        assert_eq!(encode(&decoded), pen_bytes);
+    }
+
+    #[test]
+    fn opacity_costs_a_byte_per_stroke() {
+        let opaque = stroke(1, Color::BLACK, 20);
+        let mut highlighter = opaque.clone();
+        highlighter.header.tool = Tool::Highlighter;
+        highlighter.header.opacity = 100;
+        let bytes = encode(&[highlighter.clone()]);
+        assert_eq!(bytes.len(), encode(&[opaque]).len() + 1);
+        assert_eq!(decode(&bytes).unwrap()[0].header, highlighter.header);
+    }
+
+    #[test]
+    fn smaller_than_bincode() {
+        // A 100ms batch and a whole drawing
+        for strokes in [
+            vec![stroke(3, PALETTE[3], 6)],
+            (0..20).map(|id| stroke(id, PALETTE[2], 80)).collect(),
+        ]
+        .iter()
+        {
+            let compact = encode(strokes).len();
+            let bincode_strokes = bincode::serialize(strokes).unwrap().len();
+            let bincode_points = bincode::serialize(&legacy(strokes)).unwrap().len();
+            assert!(
+                compact * 4 < bincode_strokes,
+                "{} vs {}",
+                compact,
+                bincode_strokes
+            );
+            assert!(
+                compact * 8 < bincode_points,
+                "{} vs {}",
+                compact,
+                bincode_points
+            );
+        }
+    }
+
+    #[test]
+    fn works_inside_bincode_messages() {
+        let message = PlayerMessage::AddPoints(vec![stroke(1, Color::BLACK, 10)]);
+        let bytes = bincode::serialize(&message).unwrap();
+        match bincode::deserialize(&bytes).unwrap() {
+            PlayerMessage::AddPoints(strokes) => assert_eq!(strokes[0].points.len(), 10),
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        // Already at the logical resolution, so it decodes back equal
+        let quantized = decode(&encode(&[stroke(2, PALETTE[3], 10)])).unwrap();
+        let redo = DrawControl::Redo(quantized[0].clone());
+        let bytes = bincode::serialize(&redo).unwrap();
+        assert!(
+            bytes.len()
+                < bincode::serialize(&legacy(&[stroke(2, PALETTE[3], 10)]))
+                    .unwrap()
+                    .len()
+        );
+        assert_eq!(bincode::deserialize::<DrawControl>(&bytes).unwrap(), redo);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let bytes = encode(&[stroke(1, Color::BLACK, 10)]);
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(decode(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(
+            decode(&[VERSION + 1, 0]),
+            Err(DecodeError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(
+            decode(&[VERSION, 1, 0, 9]),
+            Err(DecodeError::UnknownTool(9))
+        );
+        assert_eq!(
+            decode(&[VERSION, 1, 0, 0, 0, 200]),
+            Err(DecodeError::UnknownColor(200))
+        );
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        let header = |id: u64| {
+            let mut bytes = vec![VERSION, 1];
+            write_varint(&mut bytes, id);
+            bytes.extend_from_slice(&[0, 0, 0, 4]);
+            bytes
+        };
+        let mut bytes = header(u32::MAX as u64 + 1);
+        bytes.push(0);
+        assert_eq!(decode(&bytes), Err(DecodeError::Overflow));
+
+        // Deltas adding up past i64
+        let mut bytes = header(1);
+        bytes.push(2);
+        for _ in 0..2 {
+            write_varint(&mut bytes, zigzag(i64::MAX));
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        assert_eq!(decode(&bytes), Err(DecodeError::Overflow));
+
+        // A time past u32
+        let mut bytes = header(1);
+        bytes.extend_from_slice(&[1, 0, 0]);
+        write_varint(&mut bytes, zigzag(u32::MAX as i64 + 1));
+        assert_eq!(decode(&bytes), Err(DecodeError::Overflow));
+    }
+}
@@ -1,37 +1,83 @@
 use yew::prelude::*;
 
-use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
+use crate::render::{
+    backing_size, context_2d, draw_cached, redraw_cached, save_png, save_svg, stroke_shape,
+    svg_omissions, StrokeCache,
+};
+use crate::shortcuts::{Action, Keymap};
 use crate::socket_agent::*;
 use crate::structures::*;
 
 use web_sys::*;
+use yew::services::keyboard::{KeyListenerHandle, KeyboardService};
 
 pub struct DrawWidget {
     _socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
     canvas_ref: NodeRef,
     cursor_ref: NodeRef,
+    overlay_ref: NodeRef,
+    viewport_ref: NodeRef,
     link: ComponentLink<Self>,
     context: Option<CanvasRenderingContext2d>,
-    points: Vec<Point>,
-    undosequence: Vec<Point>,
-    todraw: Vec<Point>,
-    tosend: Vec<Point>,
+    cache: Option<StrokeCache>,
+    strokes: Vec<Stroke>,
+    /// Drawn as the canvas css background, strokes are on a transparent layer
+    background: Option<Color>,
+    redosequence: Vec<Stroke>,
+    drawn: (usize, usize),
+    tosend: Vec<Stroke>,
+    next_id: u32,
     pressed: bool,
+    pointer_id: Option<i32>,
+    /// `devicePixelRatio` the canvas was last sized for
+    dpr: f64,
+    /// `Date::now()` when the turn started, points are timed from it
+    epoch: f64,
     toolboxopen: ToolBoxOpen,
-    current_color: String,
+    current_color: Color,
+    recent_colors: Vec<Color>,
+    /// Kept apart from `current_color` so greys keep the picked hue
+    picker_hsv: (f64, f64, f64),
+    hex_input: String,
     current_width: u32,
-    is_eraser: bool,
+    current_opacity: u8,
+    current_tool: Tool,
+    shape_start: Option<Point>,
+    shape_end: Option<Point>,
+    viewport: Viewport,
     send_interval: yew::services::interval::IntervalTask,
     refresh_interval: yew::services::interval::IntervalTask,
+    _keydown: KeyListenerHandle,
     props: Props,
 }
 
+/// Pinch zoom and pan of the viewer canvas, only a CSS transform so
+/// the drawing and its coordinates stay as they are
+struct Viewport {
+    zoom: f64,
+    pan: (f64, f64),
+    /// Pointers down on the canvas with their last position in the viewport
+    pointers: Vec<(i32, f64, f64)>,
+}
+
+const MAX_ZOOM: f64 = 8_f64;
+
+const MAX_RECENT_COLORS: usize = 8;
+
+/// Sizes offered by the brush toolbox, [ and ] step through them
+const BRUSH_SIZES: [u32; 5] = [2, 5, 10, 15, 20];
+
+/// Widths offered when saving the drawing, the height follows the board
+const EXPORT_WIDTHS: [(&str, u32); 3] = [("Small", 800), ("Medium", 1600), ("Large", 3200)];
+
 pub enum ToolBoxOpen {
     Brush,
     Eraser,
     Color,
+    Shape,
+    Export,
     None,
 }
 
@@ -42,36 +88,54 @@ pub enum Msg {
     CanvasResize,
     Refresh,
 
-    MouseDown(MouseEvent),
-    MouseUp(MouseEvent),
-    MouseMove(MouseEvent),
-    MouseExit(MouseEvent),
+    PointerDown(PointerEvent),
+    PointerUp(PointerEvent),
+    PointerMove(PointerEvent),
+    PointerLeave,
     MouseWheel(WheelEvent),
-
-    TouchStart(TouchEvent),
-    TouchMove(TouchEvent),
-    TouchEnd(TouchEvent),
-    TouchCancel(TouchEvent),
+    KeyDown(KeyboardEvent),
 
     SetToolBox(ToolBoxOpen),
 
-    SetColor(String),
+    SetColor(Color),
+    PickHsv(f64, f64, f64),
+    SetHex(String),
     SetSize(u32),
+    SetOpacity(u8),
     ToggleEraser,
     SetEraser,
     SetBrush,
+    SetFill,
+    SetHighlighter,
+    SetShape(Tool),
+    SetBackground,
 
     ClearDoc,
     Undo,
+    Redo,
+    SaveImage(u32),
+    SaveSvg,
 
     SendData,
-    SetData(Vec<Point>),
+    SetData(Vec<Stroke>),
+    ApplyControl(DrawControl),
+    /// Whole drawing from the server after reconnecting
+    Resync(GameData),
 }
 
 #[derive(Properties, Clone, Debug)]
 pub struct Props {
     pub draw: bool,
-    pub initialpoints: Vec<Point>,
+    pub initialstrokes: Vec<Stroke>,
+    #[prop_or_default]
+    pub background: Option<Color>,
+    /// Gets the whole drawing when the widget goes away at the end of a turn
+    #[prop_or_default]
+    pub onfinished: Callback<(Vec<Stroke>, Color)>,
+    /// Name of saved images, without the extension
+    pub exportname: String,
+    #[prop_or_default]
+    pub keymap: Keymap,
 }
 
 impl Component for DrawWidget {
@@ -90,6 +154,17 @@ impl Component for DrawWidget {
                         Msg::SetData(pts)
                     }
                 }
+                SocketMessage::DrawControl(control) => {
+                    if draw {
+                        Msg::Ignore
+                    } else {
+                        Msg::ApplyControl(control)
+                    }
+                }
+                SocketMessage::LobbyJoined(lobby) => match lobby.state {
+                    State::Game(_, _, data) if !draw => Msg::Resync(data),
+                    _ => Msg::Ignore,
+                },
                 _ => Msg::Ignore,
             },
             _ => Msg::Ignore,
@@ -102,24 +177,55 @@ impl Component for DrawWidget {
             std::time::Duration::from_secs(1),
             _link.callback(|_| Msg::Refresh),
         );
+        let keydown = KeyboardService::register_key_down(
+            &yew::utils::document(),
+            _link.callback(Msg::KeyDown),
+        );
         Self {
             _socket_agent: agent,
             canvas_ref: NodeRef::default(),
             cursor_ref: NodeRef::default(),
+            overlay_ref: NodeRef::default(),
+            viewport_ref: NodeRef::default(),
             link: _link,
             context: None,
-            points: _props.initialpoints.clone(),
-            undosequence: vec![],
-            todraw: vec![],
+            cache: None,
+            strokes: _props.initialstrokes.clone(),
+            background: _props.background,
+            redosequence: vec![],
+            drawn: (0, 0),
             tosend: vec![],
+            next_id: _props
+                .initialstrokes
+                .last()
+                .map(|stroke| stroke.id + 1)
+                .unwrap_or(0),
             pressed: false,
+            pointer_id: None,
+            dpr: yew::utils::window().device_pixel_ratio(),
+            epoch: js_sys::Date::now()
+                - _props
+                    .initialstrokes
+                    .iter()
+                    .filter_map(|stroke| stroke.points.last())
+                    .map(|point| point.time)
+                    .max()
+                    .unwrap_or(0) as f64,
             toolboxopen: ToolBoxOpen::None,
-            current_color: "black".to_string(),
+            current_color: Color::BLACK,
+            recent_colors: vec![],
+            picker_hsv: Color::BLACK.to_hsv(),
+            hex_input: Color::BLACK.to_string(),
             current_width: 2,
-            is_eraser: false,
+            current_opacity: u8::MAX,
+            current_tool: Tool::Brush,
+            shape_start: None,
+            shape_end: None,
+            viewport: Viewport::new(),
             send_interval: interval,
             props: _props,
             refresh_interval: refreshinterval,
+            _keydown: keydown,
         }
     }
 
@@ -129,30 +235,36 @@ impl Component for DrawWidget {
             Msg::Refresh => {
                 let canvas: HtmlCanvasElement =
                     self.canvas_ref.cast().expect("Not html canvas element");
-                let rect = canvas.get_bounding_client_rect();
+                let (width, height) = backing_size(&canvas);
                 let error_margin = 15;
-                let mut draw = false;
-                if canvas.width() + error_margin < rect.width() as u32 {
-                    canvas.set_width(rect.width() as u32);
+                // Moving to another monitor or zooming the page changes the ratio
+                let dpr = yew::utils::window().device_pixel_ratio();
+                let mut draw = dpr != self.dpr;
+                if draw {
+                    self.dpr = dpr;
+                    canvas.set_width(width);
+                    canvas.set_height(height);
+                }
+                if canvas.width() + error_margin < width {
+                    canvas.set_width(width);
                     draw = true;
                 }
-                if canvas.height() + error_margin < rect.height() as u32 {
-                    canvas.set_height(rect.height() as u32);
+                if canvas.height() + error_margin < height {
+                    canvas.set_height(height);
                     draw = true;
                 }
 
-                if canvas.width() > (rect.width() as u32) + error_margin {
-                    canvas.set_width(rect.width() as u32);
+                if canvas.width() > width + error_margin {
+                    canvas.set_width(width);
                     draw = true;
                 }
-                if canvas.height() > (rect.height() as u32) + error_margin {
-                    canvas.set_height(rect.height() as u32);
+                if canvas.height() > height + error_margin {
+                    canvas.set_height(height);
                     draw = true;
                 }
 
                 if draw {
-                    self.todraw.append(&mut self.points.clone());
-                    self.draw();
+                    self.redraw();
                 }
 
                 true
@@ -165,23 +277,43 @@ impl Component for DrawWidget {
                 self.initcanvas();
                 false
             }
-            Msg::MouseDown(ev) => {
-                self.mousedown(ev);
+            Msg::PointerDown(ev) => {
+                if self.props.draw {
+                    self.pointerdown(ev);
+                } else {
+                    self.gesturedown(ev);
+                }
                 false
             }
-            Msg::MouseMove(ev) => {
-                self.mousemove(ev);
+            Msg::PointerMove(ev) => {
+                if self.props.draw {
+                    self.pointermove(ev);
+                } else {
+                    self.gesturemove(ev);
+                }
                 false
             }
-            Msg::MouseUp(ev) => {
-                self.mouseup(ev);
+            Msg::PointerUp(ev) => {
+                if self.props.draw {
+                    self.pointerup(ev);
+                } else {
+                    self.gestureup(ev);
+                }
                 false
             }
-            Msg::MouseExit(ev) => {
-                self.pressed = false;
+            Msg::PointerLeave => {
                 self.hide_cursor();
                 false
             }
+            // Plain scrolling moves the page, trackpad pinches arrive with ctrl
+            Msg::MouseWheel(ev) if !self.props.draw && !ev.ctrl_key() => false,
+            Msg::MouseWheel(ev) if !self.props.draw => {
+                let at = self.viewport_pos(&ev);
+                self.viewport.zoom_at(at, (-ev.delta_y() / 500_f64).exp());
+                self.apply_viewport();
+                ev.prevent_default();
+                false
+            }
             Msg::MouseWheel(ev) => {
                 log::debug!(
                     "Change size with wheel size: {} {} {}",
@@ -204,21 +336,18 @@ impl Component for DrawWidget {
                 ev.prevent_default();
                 false
             }
-            Msg::TouchStart(ev) => {
-                self.touchstart(ev);
-                false
-            }
-            Msg::TouchMove(ev) => {
-                self.touchmove(ev);
-                false
-            }
-            Msg::TouchEnd(ev) => {
-                self.touchend(ev);
-                false
-            }
-            Msg::TouchCancel(ev) => {
-                self.touchend(ev);
-                false
+            Msg::KeyDown(ev) => {
+                if !self.props.draw || ev.alt_key() || typing() {
+                    return false;
+                }
+                let ctrl = ev.ctrl_key() || ev.meta_key();
+                match self.props.keymap.action(&ev.key(), ctrl, ev.shift_key()) {
+                    Some(action) => {
+                        ev.prevent_default();
+                        self.shortcut(action)
+                    }
+                    None => false,
+                }
             }
             Msg::SetToolBox(tool) => {
                 self.toolboxopen = tool;
@@ -226,75 +355,138 @@ impl Component for DrawWidget {
             }
             Msg::SetColor(color) => {
                 self.current_color = color;
+                self.picker_hsv = color.to_hsv();
+                self.hex_input = color.to_string();
                 self.toolboxopen = ToolBoxOpen::None;
                 true
             }
+            Msg::PickHsv(hue, saturation, value) => {
+                self.picker_hsv = (hue, saturation, value);
+                self.current_color = Color::from_hsv(hue, saturation, value);
+                self.hex_input = self.current_color.to_string();
+                true
+            }
+            Msg::SetHex(text) => {
+                if let Ok(color) = text.parse::<Color>() {
+                    self.current_color = color;
+                    self.picker_hsv = color.to_hsv();
+                }
+                self.hex_input = text;
+                true
+            }
             Msg::SetSize(size) => {
                 self.current_width = size;
                 self.toolboxopen = ToolBoxOpen::None;
                 true
             }
+            Msg::SetOpacity(opacity) => {
+                self.current_opacity = opacity;
+                true
+            }
             Msg::ToggleEraser => {
-                self.is_eraser = !self.is_eraser;
+                self.current_tool = if self.current_tool == Tool::Eraser {
+                    Tool::Brush
+                } else {
+                    Tool::Eraser
+                };
                 true
             }
             Msg::SetEraser => {
-                self.is_eraser = true;
+                self.current_tool = Tool::Eraser;
                 true
             }
             Msg::SetBrush => {
-                self.is_eraser = false;
-                false
+                self.current_tool = Tool::Brush;
+                true
             }
+            Msg::SetFill => {
+                self.current_tool = Tool::Fill;
+                true
+            }
+            Msg::SetHighlighter => {
+                self.current_tool = Tool::Highlighter;
+                true
+            }
+            Msg::SetShape(tool) => {
+                self.current_tool = tool;
+                self.toolboxopen = ToolBoxOpen::None;
+                true
+            }
+            Msg::SetBackground => {
+                // Once a turn, so viewers do not see the board flash
+                if self.background.is_none() {
+                    self.background = Some(self.current_color);
+                    self.apply_background();
+                    self.send_control(DrawControl::Background(self.current_color));
+                }
+                self.toolboxopen = ToolBoxOpen::None;
+                true
+            }
+            // A stroke being drawn would continue on a cleared or undone drawing
+            Msg::ClearDoc | Msg::Undo | Msg::Redo if self.pressed => false,
             Msg::ClearDoc => {
-                self.points.clear();
-                self.resetcanvas();
+                self.strokes.clear();
+                self.redosequence.clear();
+                self.redraw();
+                self.send_control(DrawControl::Clear);
                 false
             }
             Msg::SendData => {
-                if !self.todraw.is_empty() {
-                    self.draw();
-                }
+                self.draw();
                 if self.tosend.is_empty() {
                     return true;
                 }
-                // TODO: FIX DRAW WIDGET
-                // self._socket_agent.send(
-                //     AgentInput::LobbyInput(
-                //         LobbyInputs::PeerBroadcastBinaryMessage(
-                //             bincode::serialize(&self.tosend).unwrap()
-                //         )
-                //     )
-                // );
-                self._socket_agent
-                    .send(AgentInput::Send(PlayerMessage::AddPoints(
-                        self.tosend.clone(),
-                    )));
-                self.tosend.clear();
+                self.send_points();
                 false
             }
-            Msg::SetData(mut data) => {
-                if let Some(firstrcv) = data.first() {
-                    if let Some(lastp) = self.points.last() {
-                        if lastp.id > firstrcv.id {
-                            while let Some(p) = self.points.pop() {
-                                if p.id <= firstrcv.id {
-                                    break;
-                                }
-                            }
-                            self.resetcanvas();
-                        }
-                    }
-                }
-                self.points.append(&mut data.clone());
-                self.todraw.append(&mut data);
+            Msg::SetData(data) => {
+                Stroke::merge(&mut self.strokes, data);
                 self.draw();
                 false
             }
+            Msg::ApplyControl(control) => {
+                control.apply(&mut self.strokes, &mut self.background);
+                // Redo only adds a stroke on top, clear and undo remove some
+                match control {
+                    DrawControl::Clear | DrawControl::Undo(_) => self.redraw(),
+                    DrawControl::Redo(_) => self.draw(),
+                    DrawControl::Background(_) => self.apply_background(),
+                }
+                false
+            }
+            Msg::Resync(data) => {
+                // Points and controls sent while offline never arrived
+                self.strokes = data.drawing;
+                self.background = data.background;
+                self.redosequence.clear();
+                self.apply_background();
+                self.redraw();
+                false
+            }
             Msg::Undo => {
                 self.undo();
                 false
             }
+            Msg::Redo => {
+                self.redo();
+                false
+            }
+            Msg::SaveImage(width) => {
+                self.save_image(width);
+                self.toolboxopen = ToolBoxOpen::None;
+                true
+            }
+            Msg::SaveSvg => {
+                save_svg(
+                    &self.strokes,
+                    self.background(),
+                    BOARD_WIDTH,
+                    BOARD_HEIGHT,
+                    &format!("{}.svg", self.props.exportname),
+                );
+                self.toolboxopen = ToolBoxOpen::None;
+                true
+            }
         }
     }
 
@@ -309,28 +501,34 @@ impl Component for DrawWidget {
         false
     }
 
+    fn destroy(&mut self) {
+        if !self.strokes.is_empty() {
+            self.props
+                .onfinished
+                .emit((self.strokes.clone(), self.background()));
+        }
+    }
+
     fn view(&self) -> Html {
         if self.props.draw {
             html! {
                 <>
-                    <div style="">
-
-                    <canvas style="box-sizing:content-box;border-color:black;border-style:solid;touch-action: none;width:100%;min-height:50vh;position:relative;cursor:none;" key="drawboard" onload=self.link.callback(|_|Msg::Setup)
-                        onmousedown=self.link.callback(|ev|Msg::MouseDown(ev))
-                        onmouseup=self.link.callback(|ev|Msg::MouseUp(ev))
-                        onmousemove=self.link.callback(|ev|Msg::MouseMove(ev))
-                        onmouseleave=self.link.callback(|ev|Msg::MouseExit(ev))
+                    <div style="position:relative;">
+
+                    <canvas style="box-sizing:content-box;border-color:black;border-style:solid;touch-action: none;width:100%;aspect-ratio:4/3;position:relative;cursor:none;" key="drawboard" onload=self.link.callback(|_|Msg::Setup)
+                        onpointerdown=self.link.callback(|ev|Msg::PointerDown(ev))
+                        onpointerup=self.link.callback(|ev|Msg::PointerUp(ev))
+                        onpointercancel=self.link.callback(|ev|Msg::PointerUp(ev))
+                        onpointermove=self.link.callback(|ev|Msg::PointerMove(ev))
+                        onpointerleave=self.link.callback(|_|Msg::PointerLeave)
                         onwheel=self.link.callback(|ev|Msg::MouseWheel(ev))
 
-                        ontouchstart=self.link.callback(|ev|Msg::TouchStart(ev))
-                        ontouchmove=self.link.callback(|ev|Msg::TouchMove(ev))
-                        ontouchend=self.link.callback(|ev|Msg::TouchEnd(ev))
-                        ontouchcancel=self.link.callback(|ev|Msg::TouchCancel(ev))
-
                         onresize=self.link.callback(|_|Msg::CanvasResize)
 
                     ref=self.canvas_ref.clone()>
                     </canvas>
+                    <canvas ref=self.overlay_ref.clone() style="position:absolute;top:0;left:0;pointer-events:none;">
+                    </canvas>
                     <div ref=self.cursor_ref.clone() style="display:none;width:5px;height:5px;background-color:grey;z-index:20;position:fixed;border-radius:50%;pointer-events: none;transform:translate(-50%,-50%);">
 
                     </div>
@@ -346,7 +544,7 @@ impl Component for DrawWidget {
                                     <div class="container">
                                         <div class="columns is-mobile">
                                             {
-                                                for [2,5,10,15,20].iter().map(
+                                                for BRUSH_SIZES.iter().map(
                                                     |size|{
                                                         html!{
                                                             <div class="column"
@@ -355,7 +553,7 @@ impl Component for DrawWidget {
                                                                 )
                                                             >
                                                                 {
-                                                                    brushsize(size.clone(),&self.current_color)
+                                                                    brushsize(size.clone(),self.current_color)
                                                                 }
                                                             </div>
                                                         }
@@ -363,23 +561,31 @@ impl Component for DrawWidget {
                                                 )
                                             }
                                         </div>
+                                        <label class="label is-small">
+                                            {format!("Opacity {}%", (self.current_opacity as f64 / 2.55).round())}
+                                        </label>
+                                        <input type="range" min="13" max="255" value=self.current_opacity.to_string() style="width:100%;"
+                                            oninput=self.link.callback(|ev: InputData| Msg::SetOpacity(ev.value.parse().unwrap_or(u8::MAX)))
+                                        />
                                     </div>
                                 },
-                                ToolBoxOpen::Color=>html!{
+                                ToolBoxOpen::Color=>self.colortoolbox(),
+                                ToolBoxOpen::Export=>self.exporttoolbox(),
+                                ToolBoxOpen::Shape=>html!{
                                     <div class="container">
                                         <div class="columns is-mobile">
                                             {
-                                                for vec!["black","red","green","blue","yellow"].iter().map(
-                                                    |color|{
-                                                        let color = color.clone();
+                                                for [Tool::Line,Tool::Rectangle,Tool::Ellipse].iter().map(
+                                                    |tool|{
+                                                        let tool = *tool;
                                                         html!{
                                                             <div class="column"
                                                                 onclick=self.link.callback(
-                                                                    move |_|Msg::SetColor(format!("{}",color))
+                                                                    move |_|Msg::SetShape(tool)
                                                                 )
                                                             >
                                                                 {
-                                                                    colorpallet(color)
+                                                                    shapeicon(tool)
                                                                 }
                                                             </div>
                                                         }
@@ -398,13 +604,13 @@ impl Component for DrawWidget {
                                 <div class="tabs is-toggle is-toggle-rounded">
                                 <ul>
                                 <li class= {
-                                    if self.is_eraser{
+                                    if self.current_tool != Tool::Brush{
                                         ""
                                     }else{
                                         "is-active"
                                     }
                                 }>
-                                <a
+                                <a title=self.props.keymap.hint("Brush", Action::Brush)
                                     onclick=self.link.callback(|_|Msg::SetBrush)
                                 >
                                     <span class="icon">
@@ -415,18 +621,18 @@ impl Component for DrawWidget {
                                 </a>
                                 </li>
                                 <li class= {
-                                    if !self.is_eraser{
+                                    if self.current_tool != Tool::Eraser{
                                         ""
                                     }else{
                                         "is-active"
                                     }
                                 }>
-                                <a class="level-item"
+                                <a class="level-item" title=self.props.keymap.hint("Eraser", Action::Eraser)
                                     onclick=self.link.callback(|_|Msg::SetEraser)
                                 >
                                     <span class="icon">
                                     {
-                                        if self.is_eraser{
+                                        if self.current_tool == Tool::Eraser{
                                             html!{
                                                 <svg style="width:24px;height:24px" viewBox="0 0 24 24">
                                                     <path fill="currentColor" d="M15.14,3C14.63,3 14.12,3.2 13.73,3.59L2.59,14.73C1.81,15.5 1.81,16.77 2.59,17.56L5.03,20H12.69L21.41,11.27C22.2,10.5 22.2,9.23 21.41,8.44L16.56,3.59C16.17,3.2 15.65,3 15.14,3M17,18L15,20H22V18" />
@@ -444,23 +650,72 @@ impl Component for DrawWidget {
                                     </span>
                                 </a>
                                 </li>
+                                <li class= {
+                                    if self.current_tool == Tool::Fill{
+                                        "is-active"
+                                    }else{
+                                        ""
+                                    }
+                                }>
+                                <a class="level-item" title=self.props.keymap.hint("Fill", Action::Fill)
+                                    onclick=self.link.callback(|_|Msg::SetFill)
+                                >
+                                    {
+                                        fillicon()
+                                    }
+                                </a>
+                                </li>
+                                <li class= {
+                                    if self.current_tool == Tool::Highlighter{
+                                        "is-active"
+                                    }else{
+                                        ""
+                                    }
+                                }>
+                                <a class="level-item" title=self.props.keymap.hint("Highlighter", Action::Highlighter)
+                                    onclick=self.link.callback(|_|Msg::SetHighlighter)
+                                >
+                                    {
+                                        highlightericon()
+                                    }
+                                </a>
+                                </li>
+                                <li class= {
+                                    if self.current_tool.is_shape(){
+                                        "is-active"
+                                    }else{
+                                        ""
+                                    }
+                                }>
+                                <a class="level-item"
+                                    onclick=self.link.callback(|_|Msg::SetToolBox(ToolBoxOpen::Shape))
+                                >
+                                    {
+                                        if self.current_tool.is_shape(){
+                                            shapeicon(self.current_tool)
+                                        }else{
+                                            shapeicon(Tool::Rectangle)
+                                        }
+                                    }
+                                </a>
+                                </li>
                                 </ul>
                                 </div>
                             </div>
 
                             <div class="level-right columns is-gapless is-mobile">
-                                <div class="column"
+                                <div class="column" title=self.size_hint()
                                     onclick=self.link.callback(|_|Msg::SetToolBox(ToolBoxOpen::Brush))
                                 >
                                     <div class="button is-outlined">
                                     <span class="icon">
                                         {
-                                            brushsize(self.current_width,&self.current_color)
+                                            brushsize(self.current_width,self.current_color)
                                         }
                                     </span>
                                     </div>
                                 </div>
-                                <div class="column"
+                                <div class="column" title=self.props.keymap.hint("Undo", Action::Undo)
                                     onclick=self.link.callback(|_|Msg::Undo)
                                 >
                                     <div class="button is-outlined">
@@ -469,6 +724,15 @@ impl Component for DrawWidget {
                                         }
                                     </div>
                                 </div>
+                                <div class="column" title=self.props.keymap.hint("Redo", Action::Redo)
+                                    onclick=self.link.callback(|_|Msg::Redo)
+                                >
+                                    <div class="button is-outlined">
+                                        {
+                                            redoicon()
+                                        }
+                                    </div>
+                                </div>
                                 <div class="column"
                                     onclick=self.link.callback(|_|Msg::ClearDoc)
                                 >
@@ -478,12 +742,21 @@ impl Component for DrawWidget {
                                         }
                                     </div>
                                 </div>
+                                <div class="column"
+                                    onclick=self.link.callback(|_|Msg::SetToolBox(ToolBoxOpen::Export))
+                                >
+                                    <div class="button is-outlined">
+                                        {
+                                            saveicon()
+                                        }
+                                    </div>
+                                </div>
                                 <div class="column"
                                 onclick=self.link.callback(|_|Msg::SetToolBox(ToolBoxOpen::Color))
                                 >
                                     <div class="button is-outlined">
                                     {
-                                        colorpallet(&self.current_color)
+                                        colorpallet(self.current_color)
                                     }
                                     </div>
                                 </div>
@@ -494,20 +767,47 @@ impl Component for DrawWidget {
             }
         } else {
             html! {
-                <div>
-                <canvas style="box-sizing:content-box;border-color:black;border-style:solid;touch-action: none;width:100%;height:100%;min-height:50vh;position:relative;" key="drawboard" onload=self.link.callback(|_|Msg::Setup)
+                <>
+                <div ref=self.viewport_ref.clone() style="overflow:hidden;">
+                <canvas style="box-sizing:content-box;border-color:black;border-style:solid;touch-action: none;width:100%;aspect-ratio:4/3;position:relative;transform-origin:0 0;" key="drawboard" onload=self.link.callback(|_|Msg::Setup)
+                    onpointerdown=self.link.callback(|ev|Msg::PointerDown(ev))
+                    onpointerup=self.link.callback(|ev|Msg::PointerUp(ev))
+                    onpointercancel=self.link.callback(|ev|Msg::PointerUp(ev))
+                    onpointermove=self.link.callback(|ev|Msg::PointerMove(ev))
+                    onwheel=self.link.callback(|ev|Msg::MouseWheel(ev))
 
                     onresize=self.link.callback(|_|Msg::CanvasResize)
 
                 ref=self.canvas_ref.clone()/>
 
                 </div>
+                <div class="box">
+                    {
+                        match self.toolboxopen{
+                            ToolBoxOpen::Export=>self.exporttoolbox(),
+                            _=>html!{}
+                        }
+                    }
+                    <div class="level is-mobile">
+                        <div class="level-left"></div>
+                        <div class="level-right">
+                            <div class="button is-outlined"
+                                onclick=self.link.callback(|_|Msg::SetToolBox(ToolBoxOpen::Export))
+                            >
+                                {
+                                    saveicon()
+                                }
+                            </div>
+                        </div>
+                    </div>
+                </div>
+                </>
             }
         }
     }
 }
 
-fn colorpallet(color: &str) -> Html {
+fn colorpallet(color: Color) -> Html {
     html! {
         <span class="icon" style=format!("color:{}",color)>
             <svg style="width:24px;height:24px" viewBox="0 0 24 24">
@@ -517,7 +817,7 @@ fn colorpallet(color: &str) -> Html {
     }
 }
 
-fn brushsize(size: u32, color: &str) -> Html {
+fn brushsize(size: u32, color: Color) -> Html {
     let size = size;
     html! {
         <>
@@ -538,6 +838,51 @@ fn undoicon() -> Html {
     }
 }
 
+fn highlightericon() -> Html {
+    html! {
+        <span class="icon">
+            <svg style="width:24px;height:24px" viewBox="0 0 24 24">
+                <path fill="currentColor" d="M18.5,1.15C17.97,1.15 17.46,1.34 17.07,1.73L11.26,7.55L16.91,13.2L22.73,7.39C23.5,6.61 23.5,5.35 22.73,4.56L19.89,1.73C19.5,1.34 19,1.15 18.5,1.15M10.3,8.5L4.34,14.46C3.56,15.24 3.56,16.5 4.36,17.31C3.14,18.54 1.9,19.77 0.67,21H6.33L7.19,20.14C7.97,20.9 9.22,20.89 10,20.12L15.95,14.16" />
+            </svg>
+        </span>
+    }
+}
+
+fn fillicon() -> Html {
+    html! {
+        <span class="icon">
+            <svg style="width:24px;height:24px" viewBox="0 0 24 24">
+                <path fill="currentColor" d="M19,11.5C19,11.5 17,13.67 17,15A2,2 0 0,0 19,17A2,2 0 0,0 21,15C21,13.67 19,11.5 19,11.5M5.21,10L10,5.21L14.79,10M16.56,8.94L7.62,0L6.21,1.41L8.59,3.79L3.44,8.94C2.85,9.5 2.85,10.47 3.44,11.06L8.94,16.56C9.23,16.85 9.62,17 10,17C10.38,17 10.77,16.85 11.06,16.56L16.56,11.06C17.15,10.47 17.15,9.5 16.56,8.94Z" />
+            </svg>
+        </span>
+    }
+}
+
+fn shapeicon(tool: Tool) -> Html {
+    let path = match tool {
+        Tool::Line => "M15,3V7.59L7.59,15H3V21H9V16.42L16.42,9H21V3M17,5H19V7H17M5,17H7V19H5",
+        Tool::Ellipse => "M12,6C16.41,6 20,8.69 20,12C20,15.31 16.41,18 12,18C7.59,18 4,15.31 4,12C4,8.69 7.59,6 12,6M12,4C6.5,4 2,7.58 2,12C2,16.42 6.5,20 12,20C17.5,20 22,16.42 22,12C22,7.58 17.5,4 12,4Z",
+        _ => "M4,6V19H20V6H4M18,17H6V8H18V17Z",
+    };
+    html! {
+        <span class="icon">
+            <svg style="width:24px;height:24px" viewBox="0 0 24 24">
+                <path fill="currentColor" d=path />
+            </svg>
+        </span>
+    }
+}
+
+fn redoicon() -> Html {
+    html! {
+        <span class="icon">
+            <svg style="width:24px;height:24px" viewBox="0 0 24 24">
+                <path fill="currentColor" d="M18.4,10.6C16.55,9 14.15,8 11.5,8C6.85,8 2.92,11.03 1.54,15.22L3.9,16C4.95,12.81 7.95,10.5 11.5,10.5C13.45,10.5 15.23,11.22 16.62,12.38L13,16H22V7L18.4,10.6Z" />
+            </svg>
+        </span>
+    }
+}
+
 fn cleardocicon() -> Html {
     html! {
         <span class="icon">
@@ -548,7 +893,138 @@ fn cleardocicon() -> Html {
     }
 }
 
+fn saveicon() -> Html {
+    html! {
+        <span class="icon">
+            <svg style="width:24px;height:24px" viewBox="0 0 24 24">
+                <path fill="currentColor" d="M5,20H19V18H5M19,9H15V3H9V9H5L12,16L19,9Z" />
+            </svg>
+        </span>
+    }
+}
+
 impl DrawWidget {
+    fn exporttoolbox(&self) -> Html {
+        html! {
+            <div class="buttons">
+                {
+                    for EXPORT_WIDTHS.iter().map(|(name, width)|{
+                        let width = *width;
+                        html!{
+                            <button class="button" onclick=self.link.callback(move |_|Msg::SaveImage(width))>
+                                {format!("{} {}px", name, width)}
+                            </button>
+                        }
+                    })
+                }
+                <button class="button" onclick=self.link.callback(|_|Msg::SaveSvg)>
+                    {"SVG"}
+                </button>
+                {
+                    svg_omissions(&self.strokes)
+                }
+            </div>
+        }
+    }
+
+    /// Palette, recently drawn colours and a hsv picker with a hex field
+    fn colortoolbox(&self) -> Html {
+        let (hue, saturation, value) = self.picker_hsv;
+        let swatches = |colors: &[Color]| {
+            html! {
+                <div class="columns is-mobile is-multiline is-gapless">
+                    {
+                        for colors.iter().map(|color|{
+                            let color = *color;
+                            let title = match PALETTE.iter().position(|slot| slot == &color) {
+                                Some(slot) => self.props.keymap.hint(&color.to_string(), Action::Color(slot)),
+                                None => color.to_string(),
+                            };
+                            html!{
+                                <div class="column is-narrow" title=title
+                                    onclick=self.link.callback(move |_|Msg::SetColor(color))
+                                >
+                                    { colorpallet(color) }
+                                </div>
+                            }
+                        })
+                    }
+                </div>
+            }
+        };
+        let slider = |max: f64,
+                      current: f64,
+                      gradient: String,
+                      pick: fn(f64, f64, f64, f64) -> Msg| {
+            let hsv = self.picker_hsv;
+            html! {
+                <input type="range" min="0" max=max.to_string() step="any" value=current.to_string()
+                    style=format!("width:100%;background:linear-gradient(to right,{});", gradient)
+                    oninput=self.link.callback(move |ev: InputData| {
+                        pick(ev.value.parse().unwrap_or(0_f64), hsv.0, hsv.1, hsv.2)
+                    })
+                />
+            }
+        };
+        html! {
+            <div class="container">
+                { swatches(&PALETTE) }
+                {
+                    if self.recent_colors.is_empty() {
+                        html! {}
+                    } else {
+                        swatches(&self.recent_colors)
+                    }
+                }
+                {
+                    slider(
+                        360_f64,
+                        hue,
+                        (0..=6)
+                            .map(|i| Color::from_hsv(i as f64 * 60_f64, 1_f64, 1_f64).to_string())
+                            .collect::<Vec<_>>()
+                            .join(","),
+                        |x, _, s, v| Msg::PickHsv(x, s, v),
+                    )
+                }
+                {
+                    slider(
+                        1_f64,
+                        saturation,
+                        format!("{},{}", Color::from_hsv(hue, 0_f64, value), Color::from_hsv(hue, 1_f64, value)),
+                        |x, h, _, v| Msg::PickHsv(h, x, v),
+                    )
+                }
+                {
+                    slider(
+                        1_f64,
+                        value,
+                        format!("{},{}", Color::BLACK, Color::from_hsv(hue, saturation, 1_f64)),
+                        |x, h, s, _| Msg::PickHsv(h, s, x),
+                    )
+                }
+                <div class="field has-addons">
+                    <div class="control">
+                        <span class="button is-static">{ colorpallet(self.current_color) }</span>
+                    </div>
+                    <div class="control">
+                        <input class="input" type="text" maxlength="7" value=self.hex_input.clone()
+                            oninput=self.link.callback(|ev: InputData| Msg::SetHex(ev.value))
+                        />
+                    </div>
+                    <div class="control">
+                        <button class="button" disabled=self.background.is_some()
+                            title="The background can be set once a turn"
+                            onclick=self.link.callback(|_| Msg::SetBackground)
+                        >
+                            {"Use as background"}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+
     fn initcanvas(&mut self) {
         log::debug!("Load context");
         let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
@@ -558,56 +1034,264 @@ impl DrawWidget {
             .unwrap()
             .dyn_into::<web_sys::CanvasRenderingContext2d>()
             .expect("Not canvas context");
-        self.resetcanvas();
         // canvas.set_height(canvas.width() as u32);
         self.context = Some(context);
+        self.apply_background();
+        self.cache = StrokeCache::new();
+        self.resetcanvas();
     }
 
-    fn set_point(&mut self, point: &Point) {
-        self.tosend.push(point.clone());
-        self.todraw.push(point.clone());
-        self.points.push(point.clone());
+    fn shortcut(&mut self, action: Action) -> ShouldRender {
+        let width = self.current_width;
+        let msg = match action {
+            Action::Brush => Msg::SetBrush,
+            Action::Eraser => Msg::SetEraser,
+            Action::Fill => Msg::SetFill,
+            Action::Highlighter => Msg::SetHighlighter,
+            Action::Undo => Msg::Undo,
+            Action::Redo => Msg::Redo,
+            Action::Smaller => Msg::SetSize(
+                *BRUSH_SIZES
+                    .iter()
+                    .rev()
+                    .find(|size| **size < width)
+                    .unwrap_or(&BRUSH_SIZES[0]),
+            ),
+            Action::Larger => Msg::SetSize(
+                *BRUSH_SIZES
+                    .iter()
+                    .find(|size| **size > width)
+                    .unwrap_or(&BRUSH_SIZES[BRUSH_SIZES.len() - 1]),
+            ),
+            Action::Color(slot) => match PALETTE.get(slot) {
+                Some(color) => Msg::SetColor(*color),
+                None => return false,
+            },
+        };
+        self.update(msg)
+    }
+
+    fn size_hint(&self) -> String {
+        let keymap = &self.props.keymap;
+        let mut keys = keymap.keys(Action::Smaller);
+        keys.extend(keymap.keys(Action::Larger));
+        if keys.is_empty() {
+            "Brush size".to_string()
+        } else {
+            format!("Brush size ({})", keys.join(", "))
+        }
+    }
+
+    fn current_header(&self) -> StrokeHeader {
+        // Brush sizes are picked in css pixels, as the cursor shows them
+        let line_width = self.current_width as f64 / self.css_board().scale;
+        StrokeHeader {
+            tool: self.current_tool,
+            color: self.current_color,
+            line_width: line_width.round().max(1_f64) as u32,
+            opacity: self.current_opacity,
+        }
+    }
+
+    /// The board on the canvas in css pixels, where pointer events are
+    fn css_board(&self) -> Letterbox {
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        Letterbox::fit(canvas.client_width() as f64, canvas.client_height() as f64)
+    }
+
+    fn start_stroke(&mut self, point: Point) {
+        let stroke = Stroke {
+            id: self.next_id,
+            header: self.current_header(),
+            points: vec![point],
+        };
+        if stroke.header.tool != Tool::Eraser {
+            self.recent_colors
+                .retain(|color| color != &stroke.header.color);
+            self.recent_colors.insert(0, stroke.header.color);
+            self.recent_colors.truncate(MAX_RECENT_COLORS);
+        }
+        self.next_id += 1;
+        self.redosequence.clear();
+        self.tosend.push(stroke.clone());
+        self.strokes.push(stroke);
         self.draw();
     }
 
-    fn setstroke_point(&mut self, point: &Point) {
-        self.undosequence.push(point.clone());
-        self.set_point(point);
+    fn add_point(&mut self, point: Point) {
+        if let Some(stroke) = self.strokes.last_mut() {
+            stroke.points.push(point);
+            match self.tosend.last_mut() {
+                Some(chunk) if chunk.id == stroke.id => chunk.points.push(point),
+                _ => self.tosend.push(Stroke {
+                    id: stroke.id,
+                    header: stroke.header.clone(),
+                    points: vec![point],
+                }),
+            }
+        }
+        self.draw();
     }
 
-    fn undo(&mut self) {
-        let laststart = self.undosequence.pop();
-        if let Some(lastpoint) = laststart {
-            while let Some(point) = self.points.pop() {
-                if point.id <= lastpoint.id {
-                    break;
-                }
+    /// Repeats the last point so the smoothed curve runs all the way to it
+    fn end_stroke(&mut self) {
+        let last = self
+            .strokes
+            .last()
+            .filter(|stroke| {
+                let tool = stroke.header.tool;
+                !tool.is_shape() && tool != Tool::Fill
+            })
+            .and_then(|stroke| match stroke.points[..] {
+                [.., a, b] if a == b => None,
+                [.., last] => Some(last),
+                [] => None,
+            });
+        if let Some(point) = last {
+            self.add_point(point);
+        }
+    }
+
+    fn begin_stroke(&mut self, point: Point) {
+        if self.current_tool.is_shape() {
+            self.shape_start = Some(point);
+            self.shape_end = None;
+        } else {
+            self.start_stroke(point);
+        }
+    }
+
+    fn extend_stroke(&mut self, point: Point) {
+        if let Some(start) = self.shape_start {
+            self.preview_shape(&start, &point);
+            self.shape_end = Some(point);
+        } else {
+            self.add_point(point);
+        }
+    }
+
+    fn commit_shape(&mut self, end: Option<Point>) {
+        self.clear_preview();
+        if let Some(start) = self.shape_start.take() {
+            let end = end.or_else(|| self.shape_end.take()).unwrap_or(start);
+            self.shape_end = None;
+            self.start_stroke(start);
+            self.add_point(end);
+        }
+    }
+
+    fn preview_shape(&self, start: &Point, end: &Point) {
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        let overlay: HtmlCanvasElement = self.overlay_ref.cast().expect("Not html canvas element");
+        if overlay.width() != canvas.width() || overlay.height() != canvas.height() {
+            overlay.set_width(canvas.width());
+            overlay.set_height(canvas.height());
+        }
+        let style = overlay.style();
+        style
+            .set_property("left", &format!("{}px", canvas.client_left()))
+            .expect("Cant set left");
+        style
+            .set_property("top", &format!("{}px", canvas.client_top()))
+            .expect("Cant set top");
+        style
+            .set_property("width", &format!("{}px", canvas.client_width()))
+            .expect("Cant set width");
+        style
+            .set_property("height", &format!("{}px", canvas.client_height()))
+            .expect("Cant set height");
+        if let Some(context) = context_2d(&overlay) {
+            context.clear_rect(
+                0_f64,
+                0_f64,
+                overlay.width() as f64,
+                overlay.height() as f64,
+            );
+            let header = self.current_header();
+            stroke_shape(
+                &context,
+                &header,
+                &Letterbox::of_canvas(&overlay),
+                start,
+                end,
+            );
+        }
+    }
+
+    fn clear_preview(&self) {
+        if let Some(overlay) = self.overlay_ref.cast::<HtmlCanvasElement>() {
+            if let Some(context) = context_2d(&overlay) {
+                context.clear_rect(
+                    0_f64,
+                    0_f64,
+                    overlay.width() as f64,
+                    overlay.height() as f64,
+                );
             }
         }
-        self.resetcanvas();
     }
 
-    fn mousedown(&mut self, event: MouseEvent) {
-        if let Some(context) = &self.context {
+    fn send_points(&mut self) {
+        // TODO: FIX DRAW WIDGET
+        // self._socket_agent.send(
+        //     AgentInput::LobbyInput(
+        //         LobbyInputs::PeerBroadcastBinaryMessage(
+        //             bincode::serialize(&self.tosend).unwrap()
+        //         )
+        //     )
+        // );
+        self._socket_agent
+            .send(AgentInput::Send(PlayerMessage::AddPoints(
+                self.tosend.clone(),
+            )));
+        self.tosend.clear();
+    }
+
+    /// Pending points go out first, so viewers never undo a stroke
+    /// before they have received it
+    fn send_control(&mut self, control: DrawControl) {
+        if !self.tosend.is_empty() {
+            self.send_points();
+        }
+        self._socket_agent
+            .send(AgentInput::Send(PlayerMessage::DrawControl(control)));
+    }
+
+    fn undo(&mut self) {
+        if let Some(stroke) = self.strokes.pop() {
+            let id = stroke.id;
+            self.redosequence.push(stroke);
+            self.redraw();
+            self.send_control(DrawControl::Undo(id));
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(stroke) = self.redosequence.pop() {
+            self.strokes.push(stroke.clone());
+            self.draw();
+            self.send_control(DrawControl::Redo(stroke));
+        }
+    }
+
+    fn pointerdown(&mut self, event: PointerEvent) {
+        // Only one finger or pen draws at a time
+        if self.pointer_id.is_some() {
+            return;
+        }
+        if self.context.is_some() {
             let canvas: HtmlCanvasElement =
                 self.canvas_ref.cast().expect("Not html canvas element");
-            let rect = canvas.get_bounding_client_rect();
-            // context.move_to(event.offset_x() as f64, event.offset_y() as f64);
-            let point = Point {
-                id: self.points.len() as u32,
-                x: event.offset_x() as f64,
-                y: event.offset_y() as f64,
-                width: rect.width(),
-                height: rect.height(),
-                draw: false,
-                color: self.current_color.clone(),
-                line_width: self.current_width,
-                eraser: self.is_eraser,
-            };
-
-            self.pressed = true;
-
-            self.setstroke_point(&point);
+            // Keeps the moves coming when the pointer leaves the canvas mid stroke
+            if let Err(err) = canvas.set_pointer_capture(event.pointer_id()) {
+                log::warn!("Cant capture pointer {:#?}", err);
+            }
+            self.pointer_id = Some(event.pointer_id());
+
+            // A fill is a single point, there is no stroke to continue
+            self.pressed = self.current_tool != Tool::Fill;
+
+            self.begin_stroke(event_point(&event, &self.css_board(), self.elapsed()));
         } else {
             log::warn!("Context not ready, not drawing");
         }
@@ -631,14 +1315,21 @@ impl DrawWidget {
         style
             .set_property("height", &format!("{}px", self.current_width))
             .expect("Cant set height");
-        if self.is_eraser {
-            style.set_property("background-color", "white").unwrap();
+        if self.current_tool == Tool::Eraser {
+            style
+                .set_property("background-color", &self.background().to_string())
+                .unwrap();
             style.set_property("border-color", "black").unwrap();
             style.set_property("border-width", "1px").unwrap();
             style.set_property("border-style", "solid").unwrap();
         } else {
+            let [r, g, b, _] = self.current_color.rgba();
+            let alpha = self.current_opacity as f64 / 255_f64;
             style
-                .set_property("background-color", &format!("{}", self.current_color))
+                .set_property(
+                    "background-color",
+                    &format!("rgba({},{},{},{})", r, g, b, alpha),
+                )
                 .expect("Cant set height");
             style.set_property("border-color", "black").unwrap();
             style.set_property("border-width", "1px").unwrap();
@@ -653,221 +1344,262 @@ impl DrawWidget {
             .expect("Cant set display");
     }
 
-    fn mouseup(&mut self, event: MouseEvent) {
-        if let Some(context) = &self.context {
-            self.pressed = false;
-            let canvas: HtmlCanvasElement =
-                self.canvas_ref.cast().expect("Not html canvas element");
-            let rect = canvas.get_bounding_client_rect();
-            let point = Point {
-                id: self.points.len() as u32,
-                x: event.offset_x() as f64,
-                y: event.offset_y() as f64,
-                width: rect.width(),
-                height: rect.height(),
-                draw: true,
-                color: self.current_color.clone(),
-                line_width: self.current_width,
-                eraser: self.is_eraser,
-            };
-
-            self.set_point(&point);
-        // self.draw();
-
-        // self.points.push(
-        //     Point{
-        //         x:event.offset_x() as f64,
-        //         y:event.offset_y() as f64
-        //     }
-        // );
-        // context.line_to(event.offset_x() as f64, event.offset_y() as f64);
-        // context.stroke();
+    fn pointerup(&mut self, event: PointerEvent) {
+        if self.pointer_id != Some(event.pointer_id()) {
+            return;
+        }
+        self.pointer_id = None;
+        if !self.pressed {
+            return;
+        }
+        self.pressed = false;
+        // Cancelled touches report no position, shapes then end where last previewed
+        let point = if event.type_() == "pointercancel" {
+            None
         } else {
-            log::warn!("Context not ready, not drawing");
+            // Pens report no pressure once lifted, the stroke ends as wide as it went
+            let pressure = self
+                .strokes
+                .last()
+                .and_then(|stroke| stroke.points.last())
+                .map_or(1_f64, |point| point.pressure);
+            Some(Point {
+                pressure,
+                ..event_point(&event, &self.css_board(), self.elapsed())
+            })
+        };
+        if self.shape_start.is_some() {
+            self.commit_shape(point);
+        } else {
+            if let Some(point) = point {
+                self.add_point(point);
+            }
+            self.end_stroke();
         }
     }
 
-    fn mousemove(&mut self, event: MouseEvent) {
-        self.set_cursor_pos(&event);
-        if let Some(context) = &self.context {
-            if self.pressed {
-                let canvas: HtmlCanvasElement =
-                    self.canvas_ref.cast().expect("Not html canvas element");
-                let rect = canvas.get_bounding_client_rect();
-                let point = Point {
-                    id: self.points.len() as u32,
-                    x: event.offset_x() as f64,
-                    y: event.offset_y() as f64,
-                    width: rect.width(),
-                    height: rect.height(),
-                    draw: true,
-                    color: self.current_color.clone(),
-                    line_width: self.current_width,
-                    eraser: self.is_eraser,
-                };
-
-                self.set_point(&point);
+    fn pointermove(&mut self, event: PointerEvent) {
+        if event.pointer_type() == "touch" {
+            self.hide_cursor();
+        } else {
+            self.set_cursor_pos(&event);
+        }
+        if self.context.is_some() && self.pressed && self.pointer_id == Some(event.pointer_id()) {
+            self.extend_stroke(event_point(&event, &self.css_board(), self.elapsed()));
+        }
+    }
 
-                self.draw();
-                // context.line_to(event.offset_x() as f64, event.offset_y() as f64);
-                // context.stroke();
+    fn gesturedown(&mut self, event: PointerEvent) {
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        if let Err(err) = canvas.set_pointer_capture(event.pointer_id()) {
+            log::warn!("Cant capture pointer {:#?}", err);
+        }
+        let (x, y) = self.viewport_pos(&event);
+        self.viewport.pointers.push((event.pointer_id(), x, y));
+    }
 
-                // context.begin_path();
-                // context.move_to(event.offset_x() as f64, event.offset_y() as f64);
+    /// One pointer pans, the first two pinch around their midpoint
+    fn gesturemove(&mut self, event: PointerEvent) {
+        let index = self
+            .viewport
+            .pointers
+            .iter()
+            .position(|(id, _, _)| *id == event.pointer_id());
+        let index = match index {
+            Some(index) if index < 2 => index,
+            _ => return,
+        };
+        let (x, y) = self.viewport_pos(&event);
+        let pointers = &mut self.viewport.pointers;
+        if pointers.len() == 1 {
+            let (_, lx, ly) = pointers[0];
+            pointers[0] = (event.pointer_id(), x, y);
+            self.viewport.pan_by(x - lx, y - ly);
+        } else {
+            let (_, ax, ay) = pointers[0];
+            let (_, bx, by) = pointers[1];
+            pointers[index] = (event.pointer_id(), x, y);
+            let (_, cx, cy) = pointers[0];
+            let (_, dx, dy) = pointers[1];
+            let distance = ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt();
+            let new_distance = ((cx - dx).powi(2) + (cy - dy).powi(2)).sqrt();
+            let mid = ((cx + dx) / 2_f64, (cy + dy) / 2_f64);
+            self.viewport
+                .pan_by(mid.0 - (ax + bx) / 2_f64, mid.1 - (ay + by) / 2_f64);
+            if distance > 0_f64 {
+                self.viewport.zoom_at(mid, new_distance / distance);
             }
         }
+        self.apply_viewport();
+    }
+
+    fn gestureup(&mut self, event: PointerEvent) {
+        self.viewport
+            .pointers
+            .retain(|(id, _, _)| *id != event.pointer_id());
+    }
+
+    /// Position of the event relative to the untransformed viewport
+    fn viewport_pos(&self, event: &MouseEvent) -> (f64, f64) {
+        let viewport: HtmlElement = self.viewport_ref.cast().expect("Not htmlelement");
+        let rect = viewport.get_bounding_client_rect();
+        (
+            event.client_x() as f64 - rect.left(),
+            event.client_y() as f64 - rect.top(),
+        )
+    }
+
+    fn apply_viewport(&mut self) {
+        let viewport: HtmlElement = self.viewport_ref.cast().expect("Not htmlelement");
+        self.viewport.clamp(
+            viewport.offset_width() as f64,
+            viewport.offset_height() as f64,
+        );
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        canvas
+            .style()
+            .set_property("transform", &self.viewport.css())
+            .expect("Cant set transform");
+    }
+
+    fn save_image(&self, width: u32) {
+        let height = (width as f64 * BOARD_HEIGHT / BOARD_WIDTH).round() as u32;
+        save_png(
+            &self.strokes,
+            self.background(),
+            width,
+            height,
+            &format!("{}.png", self.props.exportname),
+        );
+    }
+
+    fn background(&self) -> Color {
+        self.background.unwrap_or(Color::WHITE)
+    }
+
+    /// Shows the background under the transparent stroke layer
+    fn apply_background(&self) {
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        canvas
+            .style()
+            .set_property("background-color", &self.background().to_string())
+            .expect("Cant set background");
+    }
+
+    fn elapsed(&self) -> u32 {
+        (js_sys::Date::now() - self.epoch) as u32
     }
 
     fn resetcanvas(&mut self) {
         let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
-        let rect = canvas.get_bounding_client_rect();
-        canvas.set_width(rect.width() as u32);
-        canvas.set_height(rect.height() as u32);
+        // Setting the size also clears the canvas
+        let (width, height) = backing_size(&canvas);
+        canvas.set_width(width);
+        canvas.set_height(height);
 
-        self.todraw.clear();
-        self.todraw.append(&mut self.points.clone());
-        // self.draw();
+        self.redraw();
     }
 
+    /// Draws whatever was added to `strokes` since the last call,
+    /// `drawn` tracks the stroke and point reached so far
     fn draw(&mut self) {
         if let Some(context) = &self.context {
             let canvas: HtmlCanvasElement =
                 self.canvas_ref.cast().expect("Not html canvas element");
-            for point in self.todraw.iter() {
-                // log::debug!("{:#?}",point);
-                if point.id == 0 || !point.draw {
-                    if point.id == 0 {
-                        context.clear_rect(
-                            0_f64,
-                            0_f64,
-                            canvas.width() as f64,
-                            canvas.height() as f64,
-                        );
-                    }
-                    // context.stroke();
-                    // context.begin_path();
-                    // context.line_to(point.get_x(&canvas), point.get_y(&canvas));
-                    // context.stroke();
-                    context.move_to(point.get_x(&canvas), point.get_y(&canvas));
-                } else {
-                    context.line_to(point.get_x(&canvas), point.get_y(&canvas));
-                    if point.eraser {
-                        context.set_stroke_style(&JsValue::from_str("white"));
-                    } else {
-                        context.set_stroke_style(&JsValue::from_str(&point.color));
-                    }
-                    context
-                        .set_line_width(point.line_width as f64 * point.get_scale_factor(&canvas));
-                    context.set_line_cap("round");
-                    context.stroke();
-                    context.begin_path();
-                    context.move_to(point.get_x(&canvas), point.get_y(&canvas));
-                }
-            }
-            self.todraw.clear();
+            self.drawn = draw_cached(
+                context,
+                &canvas,
+                self.cache.as_mut(),
+                &self.strokes,
+                self.drawn,
+            );
         } else {
             log::warn!("Cant draw, no context");
         }
     }
 
-    fn touchstart(&mut self, event: TouchEvent) {
+    /// Draws the canvas again after strokes were removed or it was resized
+    fn redraw(&mut self) {
         if let Some(context) = &self.context {
             let canvas: HtmlCanvasElement =
                 self.canvas_ref.cast().expect("Not html canvas element");
-            let rect = canvas.get_bounding_client_rect();
-            let point = Point {
-                id: self.points.len() as u32,
-                x: self.get_offset_x(&event),
-                y: self.get_offset_y(&event),
-                width: rect.width(),
-                height: rect.height(),
-                draw: false,
-                color: self.current_color.clone(),
-                line_width: self.current_width,
-                eraser: self.is_eraser,
-            };
-
-            self.setstroke_point(&point);
-
-            self.pressed = true;
+            self.drawn = redraw_cached(context, &canvas, self.cache.as_mut(), &self.strokes);
         } else {
-            log::warn!("Context not ready, not drawing");
+            log::warn!("Cant draw, no context");
         }
     }
+}
 
-    fn touchend(&mut self, event: TouchEvent) {
-        if let Some(context) = &self.context {
-            self.pressed = false;
-            let canvas: HtmlCanvasElement =
-                self.canvas_ref.cast().expect("Not html canvas element");
-            let rect = canvas.get_bounding_client_rect();
-            let point = Point {
-                id: self.points.len() as u32,
-                x: self.get_offset_x(&event),
-                y: self.get_offset_y(&event),
-                width: rect.width(),
-                height: rect.height(),
-                draw: false,
-                color: self.current_color.clone(),
-                line_width: self.current_width,
-                eraser: self.is_eraser,
-            };
-
-            self.set_point(&point);
-
-        // context.line_to(self.get_offset_x(&event), self.get_offset_y(&event));
-        // context.stroke();
-        } else {
-            log::warn!("Context not ready, not drawing");
+impl Viewport {
+    fn new() -> Self {
+        Self {
+            zoom: 1_f64,
+            pan: (0_f64, 0_f64),
+            pointers: vec![],
         }
     }
 
-    fn touchmove(&mut self, event: TouchEvent) {
-        if let Some(context) = &self.context {
-            if self.pressed {
-                let canvas: HtmlCanvasElement =
-                    self.canvas_ref.cast().expect("Not html canvas element");
-                let rect = canvas.get_bounding_client_rect();
-                let point = Point {
-                    id: self.points.len() as u32,
-                    x: self.get_offset_x(&event),
-                    y: self.get_offset_y(&event),
-                    width: rect.width(),
-                    height: rect.height(),
-                    draw: true,
-                    color: self.current_color.clone(),
-                    line_width: self.current_width,
-                    eraser: self.is_eraser,
-                };
-                self.set_point(&point);
+    fn pan_by(&mut self, dx: f64, dy: f64) {
+        self.pan = (self.pan.0 + dx, self.pan.1 + dy);
+    }
 
-                self.draw();
-            }
-        }
+    /// Scales by `factor` keeping the point at `at` in place
+    fn zoom_at(&mut self, at: (f64, f64), factor: f64) {
+        let zoom = (self.zoom * factor).clamp(1_f64, MAX_ZOOM);
+        let factor = zoom / self.zoom;
+        self.pan = (
+            at.0 - (at.0 - self.pan.0) * factor,
+            at.1 - (at.1 - self.pan.1) * factor,
+        );
+        self.zoom = zoom;
     }
 
-    fn get_offset_x(&self, event: &TouchEvent) -> f64 {
-        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
-        let rect = canvas.get_bounding_client_rect();
-        let ev0 = event.target_touches().get(0);
-        if let Some(ev) = ev0 {
-            let pgx = ev.client_x();
-            (pgx as f64) - rect.left()
-        } else {
-            log::warn!("No touch 0 in event {:#?}", event);
-            0_f64
-        }
+    /// Keeps the zoomed canvas covering the whole viewport
+    fn clamp(&mut self, width: f64, height: f64) {
+        self.pan = (
+            self.pan.0.clamp(width * (1_f64 - self.zoom), 0_f64),
+            self.pan.1.clamp(height * (1_f64 - self.zoom), 0_f64),
+        );
     }
-    fn get_offset_y(&self, event: &TouchEvent) -> f64 {
-        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
-        let rect = canvas.get_bounding_client_rect();
-        let ev0 = event.target_touches().get(0);
-        if let Some(ev) = ev0 {
-            let pgx = ev.client_y();
-            (pgx as f64) - rect.top()
-        } else {
-            log::warn!("No touch 0 in event {:#?}", event);
-            0_f64
-        }
+
+    fn css(&self) -> String {
+        format!(
+            "translate({}px,{}px) scale({})",
+            self.pan.0, self.pan.1, self.zoom
+        )
+    }
+}
+
+/// Pen pressure and tilt scale the line width, mouse and touch always draw at full width
+fn event_point(event: &PointerEvent, board: &Letterbox, time: u32) -> Point {
+    let pressure = if event.pointer_type() == "pen" {
+        let tilt = event.tilt_x().abs().max(event.tilt_y().abs()) as f64;
+        // A tilted pen draws with its side
+        event.pressure() as f64 * (1_f64 + tilt / 180_f64)
+    } else {
+        1_f64
+    };
+    let (x, y) = board.to_board(event.offset_x() as f64, event.offset_y() as f64);
+    Point {
+        x,
+        y,
+        pressure,
+        time,
+    }
+}
+
+/// Whether a text field has focus, keys typed there are not shortcuts
+fn typing() -> bool {
+    match yew::utils::document().active_element() {
+        Some(element) => match element.dyn_ref::<HtmlInputElement>() {
+            Some(input) => input.type_() != "range",
+            None => {
+                element.tag_name() == "TEXTAREA"
+                    || element
+                        .dyn_ref::<HtmlElement>()
+                        .is_some_and(|element| element.is_content_editable())
+            }
+        },
+        None => false,
     }
 }
@@ -0,0 +1,182 @@
+use yew::prelude::*;
+
+use web_sys::HtmlCanvasElement;
+
+use crate::render::{context_2d, draw_strokes, export_name, save_png, save_svg, svg_omissions};
+use crate::replay::Replay;
+use crate::structures::*;
+
+/// Every drawing of the game, shown once it is over
+pub struct Gallery {
+    link: ComponentLink<Self>,
+    thumbnails: Vec<NodeRef>,
+    selected: Option<usize>,
+    props: Props,
+}
+
+pub enum Msg {
+    Open(usize),
+    Close,
+    SavePng(usize),
+    SaveSvg(usize),
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct Props {
+    pub turns: Vec<TurnRecord>,
+    pub room: String,
+}
+
+const THUMBNAIL_WIDTH: u32 = 240;
+
+const EXPORT_WIDTH: u32 = 1600;
+
+impl Component for Gallery {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_props: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            link: _link,
+            thumbnails: _props.turns.iter().map(|_| NodeRef::default()).collect(),
+            selected: None,
+            props: _props,
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        match _msg {
+            Msg::Open(index) => {
+                self.selected = Some(index);
+                true
+            }
+            Msg::Close => {
+                self.selected = None;
+                true
+            }
+            Msg::SavePng(index) => {
+                if let Some(turn) = self.props.turns.get(index) {
+                    let (width, height) = board_size(EXPORT_WIDTH);
+                    let filename = export_name(&self.props.room, turn.turn, &turn.drawer);
+                    save_png(
+                        &turn.drawing,
+                        turn.background,
+                        width,
+                        height,
+                        &format!("{}.png", filename),
+                    );
+                }
+                false
+            }
+            Msg::SaveSvg(index) => {
+                if let Some(turn) = self.props.turns.get(index) {
+                    let (width, height) = board_size(EXPORT_WIDTH);
+                    let filename = export_name(&self.props.room, turn.turn, &turn.drawer);
+                    save_svg(
+                        &turn.drawing,
+                        turn.background,
+                        width as f64,
+                        height as f64,
+                        &format!("{}.svg", filename),
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        for (turn, thumbnail) in self.props.turns.iter().zip(self.thumbnails.iter()) {
+            let canvas: HtmlCanvasElement = match thumbnail.cast() {
+                Some(canvas) => canvas,
+                None => continue,
+            };
+            if let Some(context) = context_2d(&canvas) {
+                // Setting the size clears what the last render drew
+                canvas.set_width(canvas.width());
+                draw_strokes(&context, &canvas, &turn.drawing, (0, 0));
+            }
+        }
+    }
+
+    fn change(&mut self, _props: Self::Properties) -> ShouldRender {
+        // The drawer's own copy of the last drawing arrives after the game ended
+        if self.props.turns == _props.turns {
+            return false;
+        }
+        self.thumbnails = _props.turns.iter().map(|_| NodeRef::default()).collect();
+        self.props = _props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div class="container my-3">
+                <h2 class="title has-text-centered">{"Gallery"}</h2>
+                {
+                    match self.selected.and_then(|index| self.props.turns.get(index).map(|turn| (index, turn))) {
+                        Some((index, turn)) => html! {
+                            <div class="box">
+                                <div class="level is-mobile">
+                                    <div class="level-left">
+                                        <h3 class="subtitle">{format!("{} by {}", turn.word, turn.drawer)}</h3>
+                                    </div>
+                                    <div class="level-right">
+                                        <div class="buttons">
+                                            <button class="button" onclick=self.link.callback(move |_|Msg::SavePng(index))>{"PNG"}</button>
+                                            <button class="button" onclick=self.link.callback(move |_|Msg::SaveSvg(index))>{"SVG"}</button>
+                                            <button class="delete" onclick=self.link.callback(|_|Msg::Close)></button>
+                                        </div>
+                                    </div>
+                                </div>
+                                { svg_omissions(&turn.drawing) }
+                                <Replay key=index.to_string() strokes=turn.drawing.clone() background=turn.background />
+                            </div>
+                        },
+                        None => html! {},
+                    }
+                }
+                <div class="columns is-multiline is-mobile">
+                {
+                    for self.props.turns.iter().zip(self.thumbnails.iter()).enumerate().map(|(index, (turn, thumbnail))|{
+                        let dpr = yew::utils::window().device_pixel_ratio();
+                        let (width, height) = board_size((THUMBNAIL_WIDTH as f64 * dpr) as u32);
+                        html!{
+                            <div class="column is-half-mobile is-one-quarter-tablet">
+                                <div class="card" style="cursor:pointer;" onclick=self.link.callback(move |_|Msg::Open(index))>
+                                    <div class="card-image">
+                                        <canvas ref=thumbnail.clone() width=width.to_string() height=height.to_string()
+                                            style=format!("width:100%;background-color:{};", turn.background)>
+                                        </canvas>
+                                    </div>
+                                    <div class="card-content">
+                                        <p class="title is-6">{&turn.word}</p>
+                                        <p class="subtitle is-7">{format!("by {}", turn.drawer)}</p>
+                                        <p class="is-size-7">
+                                        {
+                                            if turn.guessed.is_empty() {
+                                                "Nobody guessed it".to_string()
+                                            } else {
+                                                format!("Guessed by {}", turn.guessed.join(", "))
+                                            }
+                                        }
+                                        </p>
+                                    </div>
+                                </div>
+                            </div>
+                        }
+                    })
+                }
+                </div>
+            </div>
+        }
+    }
+}
+
+/// Size of a picture `width` pixels wide with the board's proportions
+fn board_size(width: u32) -> (u32, u32) {
+    (
+        width,
+        (width as f64 * BOARD_HEIGHT / BOARD_WIDTH).round() as u32,
+    )
+}
@@ -2,8 +2,11 @@ use yew::prelude::*;
 
 use crate::chat_history::ChatHistory;
 use crate::draw_widget::DrawWidget;
+use crate::gallery::Gallery;
 use crate::notification_agent::*;
 use crate::peer::PeerWidget;
+use crate::render::export_name;
+use crate::replay::Replay;
 use crate::socket_agent::*;
 use crate::structures::*;
 
@@ -19,6 +22,11 @@ pub struct Game {
     notif_agent: Box<dyn yew::Bridge<NotificationAgent>>,
     lobby: Lobby,
     selfid: String,
+    /// Counts the turns since joining, names saved drawings and keys the DrawWidget
+    turn: u32,
+    /// Drawing of the turn that just ended, with its background
+    replay: Option<(Vec<Stroke>, Color)>,
+    turns: Vec<TurnRecord>,
     link: ComponentLink<Self>,
 }
 
@@ -27,7 +35,14 @@ pub enum Msg {
     PlayerJoin(Player),
     PlayerDisconnect(Player),
     LeaderChange(State),
+    /// Fresh state of the room after reconnecting
+    Refresh(Lobby),
     ChooseWord(String),
+    AddPoints(Vec<Stroke>),
+    DrawControl(DrawControl),
+    /// Complete drawing of the turn with this number, once its DrawWidget is gone
+    TurnFinished(u32, (Vec<Stroke>, Color)),
+    CloseReplay,
 }
 
 #[derive(Properties, Clone, Debug)]
@@ -52,6 +67,9 @@ impl Component for Game {
                         Msg::LeaderChange(state)
                     }
                     SocketMessage::ScoreChange(state) => Msg::LeaderChange(state),
+                    SocketMessage::AddPoints(strokes) => Msg::AddPoints(strokes),
+                    SocketMessage::DrawControl(control) => Msg::DrawControl(control),
+                    SocketMessage::LobbyJoined(lobby) => Msg::Refresh(lobby),
                     _ => Msg::Ignore,
                 }
             }
@@ -64,6 +82,9 @@ impl Component for Game {
             lobby: _props.lobby,
             link: _link,
             selfid: _props.selfid,
+            turn: 1,
+            replay: None,
+            turns: vec![],
         }
     }
 
@@ -71,9 +92,39 @@ impl Component for Game {
         match _msg {
             Msg::Ignore => false,
             Msg::LeaderChange(leader) => {
+                if let State::Game(old, _, data) = &self.lobby.state {
+                    // The same player may draw the next turn too, then only the word
+                    // tells, going back to choosing or changing to another one
+                    let turn_over = match &leader {
+                        State::Game(new, _, new_data) => {
+                            old != new
+                                || match (&data.word, &new_data.word) {
+                                    (WordState::Word(word), WordState::Word(new_word)) => {
+                                        word != new_word
+                                    }
+                                    (WordState::Word(_), WordState::ChoseWords(_)) => true,
+                                    (WordState::ChoseWords(_), _) => false,
+                                }
+                        }
+                        State::Lobby(_) => true,
+                    };
+                    if turn_over {
+                        if let Some(record) = self.turn_record(old, data) {
+                            self.turns.push(record);
+                        }
+                        // Also when the game ends, the last drawer may lead the lobby
+                        // and the DrawWidget is only replaced when its key changes
+                        self.turn += 1;
+                    }
+                }
                 self.lobby.state = leader;
                 true
             }
+            Msg::Refresh(lobby) => {
+                self.lobby.players = lobby.players;
+                // Turns that ended while offline are counted like any other
+                self.update(Msg::LeaderChange(lobby.state))
+            }
             Msg::PlayerJoin(p) => {
                 self.lobby.players.insert(p.id.clone(), p);
                 true
@@ -87,6 +138,33 @@ impl Component for Game {
                     .send(AgentInput::Send(PlayerMessage::WordChosen(word)));
                 true
             }
+            // Keeps the drawing in the state complete for viewers, it is handed to
+            // the next DrawWidget and saved in the turn record
+            Msg::AddPoints(strokes) => {
+                if let State::Game(_, _, data) = &mut self.lobby.state {
+                    Stroke::merge(&mut data.drawing, strokes);
+                }
+                false
+            }
+            Msg::DrawControl(control) => {
+                if let State::Game(_, _, data) = &mut self.lobby.state {
+                    control.apply(&mut data.drawing, &mut data.background);
+                }
+                false
+            }
+            Msg::TurnFinished(turn, (strokes, background)) => {
+                // Same drawing as its record but complete up to the last point
+                if let Some(record) = self.turns.iter_mut().find(|record| record.turn == turn) {
+                    record.drawing = strokes.clone();
+                    record.background = background;
+                }
+                self.replay = Some((strokes, background));
+                true
+            }
+            Msg::CloseReplay => {
+                self.replay = None;
+                true
+            }
         }
     }
 
@@ -97,10 +175,10 @@ impl Component for Game {
     fn view(&self) -> Html {
         let leader = self.lobby.state.leader().to_string();
 
-        let points = {
+        let (strokes, background) = {
             match &self.lobby.state {
-                State::Game(_, _, pt) => pt.drawing.clone(),
-                State::Lobby(_) => vec![],
+                State::Game(_, _, pt) => (pt.drawing.clone(), pt.background),
+                State::Lobby(_) => (vec![], None),
             }
         };
         let time = {
@@ -201,6 +279,16 @@ impl Component for Game {
             }
         };
         let state = self.lobby.state.clone();
+        let turn = self.turn;
+        let exportname = export_name(
+            &self.lobby.id,
+            self.turn,
+            self.lobby
+                .players
+                .get(&leader)
+                .map(|player| player.name.as_str())
+                .unwrap_or_default(),
+        );
         html! {
             <div class="section py-2">
             <div class="">
@@ -236,11 +324,39 @@ impl Component for Game {
             {
                 wordc
             }
+            {
+                match &self.lobby.state {
+                    State::Lobby(_) if !self.turns.is_empty() => html! {
+                        <Gallery turns=self.turns.clone() room=self.lobby.id.clone() />
+                    },
+                    _ => html! {},
+                }
+            }
             <div class="columns">
                 <div class="column  is-three-quarters-widescreen">
-                    <div key=leader.clone()+&draw.to_string() style="">
-                        <DrawWidget draw=draw initialpoints=points />
+                    <div key=format!("{}{}{}", leader, draw, self.turn) style="">
+                        <DrawWidget draw=draw initialstrokes=strokes background=background exportname=exportname
+                            onfinished=self.link.callback(move |finished| Msg::TurnFinished(turn, finished)) />
                     </div>
+                    {
+                        if let (Some((strokes, background)), State::Game(_, _, _)) = (&self.replay, &self.lobby.state) {
+                            html! {
+                                <div class="mt-3">
+                                    <div class="level is-mobile mb-1">
+                                        <div class="level-left">
+                                            <h2 class="subtitle">{"Last drawing"}</h2>
+                                        </div>
+                                        <div class="level-right">
+                                            <button class="delete" onclick=self.link.callback(|_|Msg::CloseReplay)></button>
+                                        </div>
+                                    </div>
+                                    <Replay key=self.turn.to_string() strokes=strokes.clone() background=*background />
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                 </div>
 
                 <div class="column">
@@ -252,6 +368,29 @@ impl Component for Game {
     }
 }
 impl Game {
+    fn turn_record(&self, drawer: &str, data: &GameData) -> Option<TurnRecord> {
+        let word = match &data.word {
+            WordState::Word(word) => word.clone(),
+            // The word was never chosen, nothing was drawn
+            WordState::ChoseWords(_) => return None,
+        };
+        let name = |id: &str| {
+            self.lobby
+                .players
+                .get(id)
+                .map(|player| player.name.clone())
+                .unwrap_or_default()
+        };
+        Some(TurnRecord {
+            turn: self.turn,
+            drawer: name(drawer),
+            word,
+            guessed: data.guessed.iter().map(|id| name(id)).collect(),
+            drawing: data.drawing.clone(),
+            background: data.background.unwrap_or(Color::WHITE),
+        })
+    }
+
     fn new_turn(&mut self, turn: &u32) {
         // if turn==&self.lobby.selfid{
         //     use crate::data::WORDS;
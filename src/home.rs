@@ -16,6 +16,8 @@ pub struct Home {
     room_id: String,
     link: ComponentLink<Self>,
     is_connecting: bool,
+    /// Why the server ended the last attempt, shown until the next one
+    closed: Option<CloseCodes>,
     socket_agent: Box<dyn yew::Bridge<SocketAgent>>,
     props: Props,
 }
@@ -38,6 +40,9 @@ pub enum Msg {
     Disconnected,
     ErrorConnecting,
     Connect,
+    Closed(CloseCodes),
+    CreateInstead,
+    DismissClosed,
     Ignore,
     LobbyJoined(Lobby),
     NameChange(String),
@@ -54,11 +59,14 @@ impl Component for Home {
 
             AgentOutput::SocketMessage(msg) => match msg {
                 SocketMessage::LobbyJoined(lobby) => Msg::LobbyJoined(lobby),
-                SocketMessage::Close(_) => Msg::Disconnected,
+                SocketMessage::Close(code) => Msg::Closed(code),
                 _ => Msg::Ignore,
             },
             AgentOutput::SocketDisconnected => Msg::Disconnected,
             AgentOutput::SocketErrorConnecting => Msg::ErrorConnecting,
+            AgentOutput::SocketReconnecting(_)
+            | AgentOutput::SocketReconnected
+            | AgentOutput::Latency(_, _) => Msg::Ignore,
         }));
         Home {
             name: "".to_string(),
@@ -66,6 +74,7 @@ impl Component for Home {
             link: _link,
             socket_agent: agent,
             is_connecting: false,
+            closed: None,
             props: _props,
         }
     }
@@ -85,6 +94,7 @@ impl Component for Home {
                     false
                 } else {
                     self.is_connecting = true;
+                    self.closed = None;
                     self.socket_agent
                         .send(AgentInput::Connect(SIGNAL_URL.to_string()));
                     true
@@ -97,6 +107,7 @@ impl Component for Home {
                     .send(AgentInput::Send(PlayerMessage::Initialize(
                         uid,
                         self.name.to_string(),
+                        PROTOCOL_VERSION,
                     )));
                 if self.room_id.is_empty() {
                     self.socket_agent
@@ -113,6 +124,19 @@ impl Component for Home {
                 self.is_connecting = false;
                 true
             }
+            Msg::Closed(code) => {
+                self.is_connecting = false;
+                self.closed = Some(code);
+                true
+            }
+            Msg::CreateInstead => {
+                self.room_id.clear();
+                self.update(Msg::Connect)
+            }
+            Msg::DismissClosed => {
+                self.closed = None;
+                true
+            }
             Msg::ErrorConnecting => {
                 self.is_connecting = false;
                 true
@@ -151,6 +175,9 @@ impl Component for Home {
                         avatar(&self.name)
                     }
 
+                    {
+                        self.closed_notice()
+                    }
                     <div class="container mt-2">
                         <fieldset disabled=self.is_connecting>
                         <div class="field">
@@ -187,3 +214,35 @@ impl Component for Home {
         }
     }
 }
+
+impl Home {
+    /// What went wrong on the last attempt and what the player can do about it
+    fn closed_notice(&self) -> Html {
+        let code = match self.closed {
+            Some(code) => code,
+            None => return html! {},
+        };
+        let (text, action, msg): (String, &str, fn() -> Msg) = match code {
+            CloseCodes::CantLoinLobbyDoestExist => (
+                format!("Room {} does not exist, create it instead?", self.room_id),
+                "Create a room",
+                || Msg::CreateInstead,
+            ),
+            CloseCodes::NewSessionOpened => {
+                (format!("{}, play here instead?", code), "Take over", || {
+                    Msg::Connect
+                })
+            }
+            // The update screen covers the page
+            CloseCodes::IncompatibleVersion(_) => return html! {},
+            _ => (code.to_string(), "Try again", || Msg::Connect),
+        };
+        html! {
+            <div class="notification is-warning mt-2" style="max-width:20rem;">
+                <button class="delete" onclick=self.link.callback(|_|Msg::DismissClosed)></button>
+                <p>{text}</p>
+                <button class="button is-small mt-2" onclick=self.link.callback(move |_|msg())>{action}</button>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Round trip time to the server, measured with the pings `App` sends.
+//!
+//! Pings carry no id, so pongs are matched to pings in the order they
+//! were sent. The time is smoothed like TCP does and the jitter is the
+//! mean deviation between samples, as RTP computes it.
+
+use std::collections::VecDeque;
+
+use crate::structures::Latency;
+
+/// Pings without a pong after this long are taken as lost
+const PING_TIMEOUT_MS: f64 = 5_000_f64;
+
+const MAX_PENDING: usize = 4;
+
+#[derive(Default)]
+pub struct LatencyMeter {
+    /// Send times of the pings waiting for a pong, oldest first
+    pending: VecDeque<f64>,
+    last_rtt: Option<f64>,
+    smoothed: f64,
+    jitter: f64,
+    /// Pongs received since the last reset
+    pub samples: u32,
+}
+
+impl LatencyMeter {
+    pub fn ping_sent(&mut self, now: f64) {
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(now);
+    }
+
+    /// The latency so far, including this pong, if it answers a ping
+    pub fn pong_received(&mut self, now: f64) -> Option<Latency> {
+        while self
+            .pending
+            .front()
+            .is_some_and(|sent| now - sent > PING_TIMEOUT_MS)
+        {
+            self.pending.pop_front();
+        }
+        let rtt = now - self.pending.pop_front()?;
+        match self.last_rtt {
+            Some(last) => {
+                self.smoothed += (rtt - self.smoothed) / 8_f64;
+                self.jitter += ((rtt - last).abs() - self.jitter) / 16_f64;
+            }
+            None => self.smoothed = rtt,
+        }
+        self.last_rtt = Some(rtt);
+        self.samples += 1;
+        Some(Latency {
+            rtt: self.smoothed.round() as u32,
+            jitter: self.jitter.round() as u32,
+        })
+    }
+
+    /// Forgets everything, pongs for pings sent on a closed socket never come
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::Quality;
+
+    #[test]
+    fn smooths_round_trips() {
+        let mut meter = LatencyMeter::default();
+        assert_eq!(meter.pong_received(10_f64), None);
+
+        meter.ping_sent(0_f64);
+        let first = meter.pong_received(80_f64).unwrap();
+        assert_eq!(first, Latency { rtt: 80, jitter: 0 });
+
+        meter.ping_sent(1000_f64);
+        let second = meter.pong_received(1160_f64).unwrap();
+        assert_eq!(second, Latency { rtt: 90, jitter: 5 });
+        assert_eq!(meter.samples, 2);
+    }
+
+    #[test]
+    fn pongs_answer_pings_in_order() {
+        let mut meter = LatencyMeter::default();
+        meter.ping_sent(0_f64);
+        meter.ping_sent(1000_f64);
+        assert_eq!(meter.pong_received(1050_f64).unwrap().rtt, 1050);
+        assert_eq!(meter.pong_received(1100_f64).unwrap().rtt, 931);
+
+        // A lost ping is not matched to a later pong
+        meter.reset();
+        meter.ping_sent(0_f64);
+        meter.ping_sent(6000_f64);
+        assert_eq!(meter.pong_received(6100_f64).unwrap().rtt, 100);
+    }
+
+    #[test]
+    fn quality_follows_rtt_and_jitter() {
+        let quality = |rtt, jitter| Latency { rtt, jitter }.quality();
+        assert_eq!(quality(60, 5), Quality::Good);
+        assert_eq!(quality(200, 5), Quality::Fair);
+        assert_eq!(quality(60, 50), Quality::Fair);
+        assert_eq!(quality(500, 5), Quality::Poor);
+        assert_eq!(quality(60, 150), Quality::Poor);
+    }
+}
@@ -1,17 +1,25 @@
-#![recursion_limit = "1024"]
+#![recursion_limit = "2048"]
 mod app;
 mod avatar;
 mod chat_history;
+mod codec;
 mod draw_widget;
+mod gallery;
 mod gameroom;
 mod home;
+mod latency;
 mod notification_agent;
 mod notification_widget;
+mod outbox;
 mod peer;
+mod render;
+mod replay;
 mod room;
 mod room_mediator;
+mod shortcuts;
 mod socket_agent;
 mod structures;
+mod svg;
 
 use wasm_bindgen::prelude::*;
 
@@ -0,0 +1,184 @@
+//! Messages for the server held back while the socket is closed.
+//!
+//! They are sent in order once the agent is back in the room, without the
+//! drawing of a turn that passed on in the meantime. What is kept depends on
+//! the kind of message: drawing batches are joined into one, pings, latency
+//! reports and audio are only worth something right away and are dropped,
+//! everything else, chat included, waits its turn.
+
+use std::collections::VecDeque;
+
+use crate::structures::{PlayerMessage, Stroke};
+
+/// The oldest messages go first when a long outage fills the queue
+const MAX_QUEUED: usize = 256;
+
+#[derive(Default)]
+pub struct Outbox {
+    queue: VecDeque<Queued>,
+}
+
+struct Queued {
+    message: PlayerMessage,
+    /// Word of the turn the player was drawing when it was sent
+    turn: Option<String>,
+}
+
+impl Outbox {
+    /// Queues `message`, sent during the turn `turn` if the player was drawing
+    pub fn push(&mut self, message: PlayerMessage, turn: Option<&str>) {
+        match message {
+            // Reconnecting says the player is still there, and the round
+            // trip measured before the outage says nothing about the next one
+            PlayerMessage::Ping | PlayerMessage::Latency(_) => {}
+            // Audio from before the outage would only talk over the game
+            PlayerMessage::AudioChat(_) => {}
+            // The agent sends these again itself when resuming the session
+            PlayerMessage::Initialize(..) | PlayerMessage::JoinLobby(_) => {}
+            // Only batches next to each other, a control in between has to
+            // apply after the points before it
+            PlayerMessage::AddPoints(strokes) => match self.queue.back_mut() {
+                Some(Queued {
+                    message: PlayerMessage::AddPoints(queued),
+                    turn: queued_turn,
+                }) if queued_turn.as_deref() == turn => Stroke::merge(queued, strokes),
+                _ => self.enqueue(PlayerMessage::AddPoints(strokes), turn),
+            },
+            message => self.enqueue(message, turn),
+        }
+    }
+
+    fn enqueue(&mut self, message: PlayerMessage, turn: Option<&str>) {
+        if self.queue.len() == MAX_QUEUED {
+            if let Some(dropped) = self.queue.pop_front() {
+                log::warn!("Outbox full, dropping {:#?}", dropped.message);
+            }
+        }
+        self.queue.push_back(Queued {
+            message,
+            turn: turn.map(str::to_string),
+        });
+    }
+
+    /// Everything queued, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = PlayerMessage> + '_ {
+        self.queue.drain(..).map(|queued| queued.message)
+    }
+
+    /// Forgets queued strokes and drawing controls unless they were drawn
+    /// in `turn`, the turn the player is drawing now
+    pub fn keep_drawing_of(&mut self, turn: Option<&str>) {
+        self.queue.retain(|queued| {
+            let drawing = matches!(
+                queued.message,
+                PlayerMessage::AddPoints(_) | PlayerMessage::DrawControl(_)
+            );
+            !drawing || (turn.is_some() && queued.turn.as_deref() == turn)
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::*;
+
+    fn stroke(id: u32, points: usize) -> Stroke {
+        Stroke::fixture(
+            id,
+            Tool::Brush,
+            Color::BLACK,
+            &vec![(0_f64, 0_f64, 1_f64); points],
+        )
+    }
+
+    #[test]
+    fn coalesces_adjacent_points() {
+        let mut outbox = Outbox::default();
+        outbox.push(PlayerMessage::AddPoints(vec![stroke(1, 3)]), Some("cat"));
+        outbox.push(
+            PlayerMessage::AddPoints(vec![stroke(1, 2), stroke(2, 4)]),
+            Some("cat"),
+        );
+        outbox.push(
+            PlayerMessage::DrawControl(DrawControl::Undo(2)),
+            Some("cat"),
+        );
+        outbox.push(PlayerMessage::AddPoints(vec![stroke(3, 1)]), Some("cat"));
+        let queued: Vec<_> = outbox.drain().collect();
+        assert_eq!(queued.len(), 3);
+        match &queued[0] {
+            PlayerMessage::AddPoints(strokes) => {
+                let counts: Vec<_> = strokes.iter().map(|s| (s.id, s.points.len())).collect();
+                assert_eq!(counts, vec![(1, 5), (2, 4)]);
+            }
+            other => panic!("expected points, got {:?}", other),
+        }
+        assert!(matches!(
+            queued[1],
+            PlayerMessage::DrawControl(DrawControl::Undo(2))
+        ));
+        assert!(matches!(&queued[2], PlayerMessage::AddPoints(strokes) if strokes[0].id == 3));
+        assert_eq!(outbox.drain().count(), 0);
+    }
+
+    #[test]
+    fn keeps_chat_and_drops_pings() {
+        let mut outbox = Outbox::default();
+        outbox.push(PlayerMessage::Ping, None);
+        outbox.push(PlayerMessage::Chat("first".to_string()), None);
+        outbox.push(PlayerMessage::Ping, None);
+        outbox.push(PlayerMessage::JoinLobby("room".to_string()), None);
+        outbox.push(PlayerMessage::Chat("second".to_string()), None);
+        let chats: Vec<_> = outbox
+            .drain()
+            .map(|message| match message {
+                PlayerMessage::Chat(text) => text,
+                other => panic!("expected chat, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(chats, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn drops_drawing_from_a_past_turn() {
+        let mut outbox = Outbox::default();
+        outbox.push(PlayerMessage::AddPoints(vec![stroke(1, 3)]), Some("cat"));
+        outbox.push(PlayerMessage::Chat("still there".to_string()), None);
+        outbox.push(PlayerMessage::DrawControl(DrawControl::Clear), Some("cat"));
+        outbox.keep_drawing_of(None);
+        let queued: Vec<_> = outbox.drain().collect();
+        assert_eq!(queued.len(), 1);
+        assert!(matches!(&queued[0], PlayerMessage::Chat(_)));
+    }
+
+    #[test]
+    fn drops_drawing_when_the_same_player_draws_again() {
+        let mut outbox = Outbox::default();
+        outbox.push(PlayerMessage::AddPoints(vec![stroke(1, 3)]), Some("cat"));
+        outbox.push(PlayerMessage::AddPoints(vec![stroke(2, 3)]), Some("dog"));
+        outbox.push(
+            PlayerMessage::DrawControl(DrawControl::Undo(1)),
+            Some("cat"),
+        );
+        outbox.keep_drawing_of(Some("dog"));
+        let queued: Vec<_> = outbox.drain().collect();
+        assert_eq!(queued.len(), 1);
+        assert!(matches!(&queued[0], PlayerMessage::AddPoints(strokes) if strokes[0].id == 2));
+    }
+
+    #[test]
+    fn drops_the_oldest_when_full() {
+        let mut outbox = Outbox::default();
+        for i in 0..MAX_QUEUED + 2 {
+            outbox.push(PlayerMessage::Chat(i.to_string()), None);
+        }
+        let queued: Vec<_> = outbox.drain().collect();
+        assert_eq!(queued.len(), MAX_QUEUED);
+        assert!(matches!(&queued[0], PlayerMessage::Chat(text) if text == "2"));
+    }
+}
@@ -33,6 +33,8 @@ pub struct PeerWidget {
     audiocache: Vec<AudioChunk>,
     audlistener: Option<EventListener>,
     peer: Player,
+    /// Last round trip measured or reported for this player
+    latency: Option<Latency>,
     tippy: Option<Tippy>,
 }
 
@@ -41,6 +43,7 @@ pub enum Msg {
 
     AudEnded,
     ReceivedAudio(String, AudioChunk),
+    Latency(String, Latency),
 }
 
 #[derive(Properties, Clone, Debug)]
@@ -57,8 +60,10 @@ impl Component for PeerWidget {
         let agent = SocketAgent::bridge(_link.callback(|data| match data {
             AgentOutput::SocketMessage(msg) => match msg {
                 SocketMessage::AudioChat(id, chnk) => Msg::ReceivedAudio(id, chnk),
+                SocketMessage::PlayerLatency(id, latency) => Msg::Latency(id, latency),
                 _ => Msg::Ignore,
             },
+            AgentOutput::Latency(id, latency) => Msg::Latency(id, latency),
             _ => Msg::Ignore,
         }));
         // agent.send(AgentInput::LobbyInput(LobbyInputs::RequestLobby));
@@ -67,6 +72,7 @@ impl Component for PeerWidget {
             link: _link,
             tippy: None,
             peer: _props.peer,
+            latency: None,
             state: _props.state,
             audiocache: vec![],
             audlistener: None,
@@ -77,6 +83,14 @@ impl Component for PeerWidget {
     fn update(&mut self, _msg: Self::Message) -> ShouldRender {
         match _msg {
             Msg::Ignore => false,
+            Msg::Latency(id, latency) => {
+                if id == self.peer.id {
+                    self.latency = Some(latency);
+                    true
+                } else {
+                    false
+                }
+            }
             Msg::AudEnded => {
                 if let Some(ad) = self.audiocache.first() {
                     let ad = ad.clone();
@@ -183,11 +197,34 @@ impl Component for PeerWidget {
                         score
                     }
                     </div>
+                    {
+                        self.latency_badge()
+                    }
                 </div>
             </>
         }
     }
 }
+impl PeerWidget {
+    fn latency_badge(&self) -> Html {
+        match self.latency {
+            Some(latency) => {
+                let class = match latency.quality() {
+                    Quality::Good => "tag is-rounded is-success",
+                    Quality::Fair => "tag is-rounded is-warning",
+                    Quality::Poor => "tag is-rounded is-danger",
+                };
+                html! {
+                    <span class=class title=format!("Jitter {} ms", latency.jitter)>
+                        {format!("{} ms", latency.rtt)}
+                    </span>
+                }
+            }
+            None => html! {},
+        }
+    }
+}
+
 impl AudioChunk {
     fn to_u8_array(&self) -> JsValue {
         let uint = js_sys::Uint8Array::from(self.data.as_slice());
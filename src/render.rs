@@ -0,0 +1,659 @@
+//! Drawing strokes on a canvas and saving them.
+//!
+//! Shared by the DrawWidget, replays and the gallery. Strokes are in board
+//! units and drawn letterboxed onto whatever canvas they are given, fills
+//! are found on the board at its own resolution so they cover the same
+//! area everywhere.
+
+use wasm_bindgen::Clamped;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::*;
+use yew::prelude::*;
+
+use crate::structures::*;
+
+/// Offscreen copy of every stroke but the last one, which may still be
+/// growing, so undo and resizing do not draw the whole drawing again
+pub struct StrokeCache {
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    /// Id and point count of the strokes on `canvas`, in order
+    strokes: Vec<(u32, usize)>,
+    /// Copies of `canvas` with the number of strokes on them, so undo can
+    /// start again from close by instead of from a blank canvas
+    snapshots: Vec<(usize, HtmlCanvasElement)>,
+}
+
+const SNAPSHOT_EVERY: usize = 16;
+
+/// Every snapshot is a full size bitmap, the oldest are dropped first
+const MAX_SNAPSHOTS: usize = 4;
+
+impl StrokeCache {
+    pub fn new() -> Option<Self> {
+        let (canvas, context) = offscreen_canvas(0, 0)?;
+        Some(Self {
+            canvas,
+            context,
+            strokes: vec![],
+            snapshots: vec![],
+        })
+    }
+
+    /// Brings the cache up to every stroke of `strokes` but the last, going
+    /// back to the nearest snapshot if some it holds were removed or cut short
+    fn update(&mut self, strokes: &[Stroke], width: u32, height: u32) {
+        if self.canvas.width() != width || self.canvas.height() != height {
+            // Setting the size clears the canvas, the snapshots are the old size
+            self.canvas.set_width(width);
+            self.canvas.set_height(height);
+            self.strokes.clear();
+            self.snapshots.clear();
+        }
+        let kept = self
+            .strokes
+            .iter()
+            .zip(strokes.iter())
+            .take_while(|((id, count), stroke)| *id == stroke.id && *count == stroke.points.len())
+            .count();
+        if kept < self.strokes.len() {
+            self.snapshots.retain(|(count, _)| *count <= kept);
+            self.context
+                .clear_rect(0_f64, 0_f64, width as f64, height as f64);
+            let restored = match self.snapshots.last() {
+                Some((count, snapshot)) => {
+                    copy_canvas(&self.context, snapshot);
+                    *count
+                }
+                None => 0,
+            };
+            self.strokes.truncate(restored);
+        }
+
+        let committed = strokes.len().saturating_sub(1);
+        while self.strokes.len() < committed {
+            let (before, stroke) = (&strokes[..self.strokes.len()], &strokes[self.strokes.len()]);
+            draw_stroke(&self.context, &self.canvas, before, stroke, 0);
+            self.strokes.push((stroke.id, stroke.points.len()));
+            if self.strokes.len().is_multiple_of(SNAPSHOT_EVERY) {
+                self.snapshot();
+            }
+        }
+    }
+
+    fn snapshot(&mut self) {
+        if let Some((canvas, context)) = offscreen_canvas(self.canvas.width(), self.canvas.height())
+        {
+            copy_canvas(&context, &self.canvas);
+            if self.snapshots.len() == MAX_SNAPSHOTS {
+                self.snapshots.remove(0);
+            }
+            self.snapshots.push((self.strokes.len(), canvas));
+        }
+    }
+}
+
+/// Redraws `strokes` offscreen over `background` and downloads it as a PNG
+pub fn save_png(strokes: &[Stroke], background: Color, width: u32, height: u32, filename: &str) {
+    let (canvas, context, (layer, layer_context)) = match (
+        offscreen_canvas(width, height),
+        offscreen_canvas(width, height),
+    ) {
+        (Some((canvas, context)), Some(layer)) => (canvas, context, layer),
+        _ => return,
+    };
+    // Strokes go on their own layer first, so erasing does not cut into the background
+    draw_strokes(&layer_context, &layer, strokes, (0, 0));
+    context.set_fill_style_str(&background.to_string());
+    context.fill_rect(0_f64, 0_f64, width as f64, height as f64);
+    copy_canvas(&context, &layer);
+
+    let filename = filename.to_string();
+    let callback = wasm_bindgen::closure::Closure::once_into_js(move |blob: JsValue| {
+        match blob.dyn_into::<Blob>() {
+            Ok(blob) => download_blob(&blob, &filename),
+            Err(_) => log::warn!("Cant encode png"),
+        }
+    });
+    if let Err(err) = canvas.to_blob_with_type(callback.unchecked_ref(), "image/png") {
+        log::warn!("Cant encode png {:#?}", err);
+    }
+}
+
+pub fn save_svg(strokes: &[Stroke], background: Color, width: f64, height: f64, filename: &str) {
+    let svg = crate::svg::render(strokes, background, width, height);
+    let parts = js_sys::Array::of1(&JsValue::from_str(&svg));
+    let options = BlobPropertyBag::new();
+    options.set_type("image/svg+xml;charset=utf-8");
+    match Blob::new_with_str_sequence_and_options(&parts, &options) {
+        Ok(blob) => download_blob(&blob, filename),
+        Err(err) => log::warn!("Cant create svg blob {:#?}", err),
+    }
+}
+
+/// Warns next to an SVG button when the export will not match the canvas
+pub fn svg_omissions(strokes: &[Stroke]) -> Html {
+    match crate::svg::omissions(strokes) {
+        Some(text) => html! { <p class="help is-warning">{text}</p> },
+        None => html! {},
+    }
+}
+
+/// How long a download's object URL stays valid after the click
+const DOWNLOAD_URL_LIFETIME_MS: u32 = 10_000;
+
+/// Saves `blob` as `filename`. Browsers refuse to download data URLs past
+/// a few megabytes, object URLs have no such limit.
+fn download_blob(blob: &Blob, filename: &str) {
+    match Url::create_object_url_with_blob(blob) {
+        Ok(url) => {
+            download(&url, filename);
+            // The download may still be reading the blob right after the click
+            gloo::timers::callback::Timeout::new(DOWNLOAD_URL_LIFETIME_MS, move || {
+                if let Err(err) = Url::revoke_object_url(&url) {
+                    log::warn!("Cant revoke download url {:#?}", err);
+                }
+            })
+            .forget();
+        }
+        Err(err) => log::warn!("Cant create download url {:#?}", err),
+    }
+}
+
+/// Saves `url` as `filename` through a temporary link
+fn download(url: &str, filename: &str) {
+    match yew::utils::document()
+        .create_element("a")
+        .map(|element| element.dyn_into::<HtmlAnchorElement>())
+    {
+        Ok(Ok(link)) => {
+            link.set_href(url);
+            link.set_download(filename);
+            link.click();
+        }
+        _ => log::warn!("Cant create download link"),
+    }
+}
+
+/// File name, without extension, of a saved drawing
+pub fn export_name(room: &str, turn: u32, drawer: &str) -> String {
+    format!("picyew-{}-turn{}-{}", room, turn, drawer)
+        .replace(|c: char| !c.is_alphanumeric() && c != '-', "_")
+}
+
+/// A canvas that is never added to the page, with its context
+fn offscreen_canvas(
+    width: u32,
+    height: u32,
+) -> Option<(HtmlCanvasElement, CanvasRenderingContext2d)> {
+    let canvas: HtmlCanvasElement = match yew::utils::document()
+        .create_element("canvas")
+        .map(|element| element.dyn_into::<HtmlCanvasElement>())
+    {
+        Ok(Ok(canvas)) => canvas,
+        _ => {
+            log::warn!("Cant create offscreen canvas");
+            return None;
+        }
+    };
+    canvas.set_width(width);
+    canvas.set_height(height);
+    match context_2d(&canvas) {
+        Some(context) => Some((canvas, context)),
+        None => {
+            log::warn!("No context for offscreen canvas");
+            None
+        }
+    }
+}
+
+/// Draws all of `from` at the same size over the canvas of `context`
+fn copy_canvas(context: &CanvasRenderingContext2d, from: &HtmlCanvasElement) {
+    if let Err(err) = context.draw_image_with_html_canvas_element(from, 0_f64, 0_f64) {
+        log::warn!("Cant copy canvas {:#?}", err);
+    }
+}
+
+pub fn context_2d(canvas: &HtmlCanvasElement) -> Option<CanvasRenderingContext2d> {
+    canvas
+        .get_context("2d")
+        .ok()
+        .flatten()
+        .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+}
+
+/// Device pixels covered by the canvas content, so lines stay sharp on HiDPI
+/// screens. Layout sizes ignore the CSS zoom of the viewer canvas.
+pub fn backing_size(canvas: &HtmlCanvasElement) -> (u32, u32) {
+    let dpr = yew::utils::window().device_pixel_ratio();
+    (
+        (canvas.client_width() as f64 * dpr).round() as u32,
+        (canvas.client_height() as f64 * dpr).round() as u32,
+    )
+}
+
+/// Draws `strokes` from the (stroke, point) cursor `drawn` on and
+/// returns where it got to
+pub fn draw_strokes(
+    context: &CanvasRenderingContext2d,
+    canvas: &HtmlCanvasElement,
+    strokes: &[Stroke],
+    drawn: (usize, usize),
+) -> (usize, usize) {
+    let (mut stroke_index, mut point_index) = drawn;
+    while let Some(stroke) = strokes.get(stroke_index) {
+        draw_stroke(
+            context,
+            canvas,
+            &strokes[..stroke_index],
+            stroke,
+            point_index,
+        );
+        point_index = stroke.points.len();
+        if stroke_index + 1 == strokes.len() {
+            break;
+        }
+        stroke_index += 1;
+        point_index = 0;
+    }
+    (stroke_index, point_index)
+}
+
+/// Draws what was added to `strokes` since `drawn` like `draw_strokes` and
+/// keeps `cache` up to date. A translucent stroke that grew is drawn again
+/// whole over the cache instead, stroking over its own end would darken it.
+pub fn draw_cached(
+    context: &CanvasRenderingContext2d,
+    canvas: &HtmlCanvasElement,
+    cache: Option<&mut StrokeCache>,
+    strokes: &[Stroke],
+    drawn: (usize, usize),
+) -> (usize, usize) {
+    let (stroke_index, point_index) = drawn;
+    let regrown = strokes.get(stroke_index).is_some_and(|stroke| {
+        stroke.header.is_translucent() && point_index > 0 && point_index < stroke.points.len()
+    });
+    match cache {
+        Some(cache) if regrown => redraw_cached(context, canvas, Some(cache), strokes),
+        Some(cache) => {
+            let drawn = draw_strokes(context, canvas, strokes, drawn);
+            cache.update(strokes, canvas.width(), canvas.height());
+            drawn
+        }
+        None => draw_strokes(context, canvas, strokes, drawn),
+    }
+}
+
+/// Clears `canvas` and draws `strokes` again, copying what `cache` holds
+pub fn redraw_cached(
+    context: &CanvasRenderingContext2d,
+    canvas: &HtmlCanvasElement,
+    cache: Option<&mut StrokeCache>,
+    strokes: &[Stroke],
+) -> (usize, usize) {
+    context.clear_rect(0_f64, 0_f64, canvas.width() as f64, canvas.height() as f64);
+    let drawn = match cache {
+        Some(cache) => {
+            cache.update(strokes, canvas.width(), canvas.height());
+            copy_canvas(context, &cache.canvas);
+            (cache.strokes.len(), 0)
+        }
+        None => (0, 0),
+    };
+    draw_strokes(context, canvas, strokes, drawn)
+}
+
+/// Draws the points of `stroke` from index `from` on, the ones before are
+/// already on the canvas. `before` are the strokes under it, which bound fills.
+fn draw_stroke(
+    context: &CanvasRenderingContext2d,
+    canvas: &HtmlCanvasElement,
+    before: &[Stroke],
+    stroke: &Stroke,
+    from: usize,
+) {
+    let header = &stroke.header;
+    let board = Letterbox::of_canvas(canvas);
+    match header.tool {
+        Tool::Fill => {
+            if let (0, Some(point)) = (from, stroke.points.first()) {
+                let mut color = header.color.rgba();
+                color[3] = header.opacity;
+                flood_fill(context, canvas, before, point, color);
+            }
+        }
+        Tool::Line | Tool::Rectangle | Tool::Ellipse => {
+            if let (0..=1, [start, end, ..]) = (from, &stroke.points[..]) {
+                stroke_shape(context, header, &board, start, end);
+            }
+        }
+        Tool::Brush | Tool::Eraser | Tool::Highlighter => {
+            let operation = match header.tool {
+                // Takes strokes away, the background under the canvas shows through
+                Tool::Eraser => "destination-out",
+                Tool::Highlighter => "multiply",
+                _ => "source-over",
+            };
+            context
+                .set_global_composite_operation(operation)
+                .expect("Cant set composite operation");
+            context.set_global_alpha(header.alpha());
+            context.set_stroke_style_str(&header.color.to_string());
+            context.set_line_cap("round");
+            context.set_line_join("round");
+            // One path per run of equal width, translucent strokes have a single
+            // width so they are one path that never covers itself twice
+            let mut width = None;
+            for piece in stroke.curve_pieces(from) {
+                let piece_width = header.width_at(piece.control.pressure) * board.scale;
+                if width != Some(piece_width) {
+                    if width.is_some() {
+                        context.stroke();
+                    }
+                    context.set_line_width(piece_width);
+                    context.begin_path();
+                    context.move_to(piece.start.get_x(&board), piece.start.get_y(&board));
+                    width = Some(piece_width);
+                }
+                context.quadratic_curve_to(
+                    piece.control.get_x(&board),
+                    piece.control.get_y(&board),
+                    piece.end.get_x(&board),
+                    piece.end.get_y(&board),
+                );
+            }
+            if width.is_some() {
+                context.stroke();
+            }
+            context.set_global_alpha(1_f64);
+            context
+                .set_global_composite_operation("source-over")
+                .expect("Cant set composite operation");
+        }
+    }
+}
+
+/// Strokes a line, rectangle or ellipse spanning the start and end corner of a drag
+pub fn stroke_shape(
+    context: &CanvasRenderingContext2d,
+    header: &StrokeHeader,
+    board: &Letterbox,
+    start: &Point,
+    end: &Point,
+) {
+    let (x0, y0) = (start.get_x(board), start.get_y(board));
+    let (x1, y1) = (end.get_x(board), end.get_y(board));
+    context.begin_path();
+    match header.tool {
+        Tool::Line => {
+            context.move_to(x0, y0);
+            context.line_to(x1, y1);
+        }
+        Tool::Rectangle => context.rect(x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs()),
+        Tool::Ellipse => {
+            if let Err(err) = context.ellipse(
+                (x0 + x1) / 2_f64,
+                (y0 + y1) / 2_f64,
+                (x1 - x0).abs() / 2_f64,
+                (y1 - y0).abs() / 2_f64,
+                0_f64,
+                0_f64,
+                std::f64::consts::PI * 2_f64,
+            ) {
+                log::warn!("Cant draw ellipse {:#?}", err);
+            }
+        }
+        Tool::Brush | Tool::Eraser | Tool::Fill | Tool::Highlighter => {}
+    }
+    context.set_stroke_style_str(&header.color.to_string());
+    context.set_line_width(header.line_width as f64 * board.scale);
+    context.set_line_cap("round");
+    context.set_line_join("round");
+    context.set_global_alpha(header.alpha());
+    context.stroke();
+    context.set_global_alpha(1_f64);
+    context.begin_path();
+}
+
+/// Fills the region of similar colour around `point` with the rgba `color`.
+/// The region is found on the board at its own resolution and scaled onto
+/// the canvas, so every client and export fills the same area whatever
+/// the size of its canvas.
+fn flood_fill(
+    context: &CanvasRenderingContext2d,
+    canvas: &HtmlCanvasElement,
+    before: &[Stroke],
+    point: &Point,
+    color: [u8; 4],
+) {
+    if point.x < 0_f64 || point.y < 0_f64 || point.x >= BOARD_WIDTH || point.y >= BOARD_HEIGHT {
+        return;
+    }
+    let (width, height) = (BOARD_WIDTH as u32, BOARD_HEIGHT as u32);
+    // A canvas at board resolution is filled in place, that is also how
+    // the fills among `before` are drawn on the raster below
+    if canvas.width() == width && canvas.height() == height {
+        if let Some(mut data) = pixels(context, width, height) {
+            fill_region(
+                &mut data,
+                width as usize,
+                height as usize,
+                point.x as usize,
+                point.y as usize,
+                color,
+            );
+            put_pixels(context, &data, width, height);
+        }
+        return;
+    }
+    let (raster, raster_context) = match offscreen_canvas(width, height) {
+        Some(offscreen) => offscreen,
+        None => return,
+    };
+    draw_strokes(&raster_context, &raster, before, (0, 0));
+    let mut data = match pixels(&raster_context, width, height) {
+        Some(data) => data,
+        None => return,
+    };
+    let filled = fill_region(
+        &mut data,
+        width as usize,
+        height as usize,
+        point.x as usize,
+        point.y as usize,
+        color,
+    );
+    // Only the filled pixels are drawn over the canvas
+    for (i, filled) in filled.iter().enumerate() {
+        if !filled {
+            data[i * 4..i * 4 + 4].copy_from_slice(&[0, 0, 0, 0]);
+        }
+    }
+    put_pixels(&raster_context, &data, width, height);
+    let board = Letterbox::of_canvas(canvas);
+    if let Err(err) = context.draw_image_with_html_canvas_element_and_dw_and_dh(
+        &raster,
+        board.left,
+        board.top,
+        BOARD_WIDTH * board.scale,
+        BOARD_HEIGHT * board.scale,
+    ) {
+        log::warn!("Cant draw fill {:#?}", err);
+    }
+}
+
+/// RGBA pixels of the top left `width` by `height` of the canvas
+fn pixels(context: &CanvasRenderingContext2d, width: u32, height: u32) -> Option<Vec<u8>> {
+    match context.get_image_data(0_f64, 0_f64, width as f64, height as f64) {
+        Ok(imagedata) => Some(imagedata.data().0),
+        Err(err) => {
+            log::warn!("Cant read canvas pixels {:#?}", err);
+            None
+        }
+    }
+}
+
+fn put_pixels(context: &CanvasRenderingContext2d, data: &[u8], width: u32, height: u32) {
+    match ImageData::new_with_u8_clamped_array_and_sh(Clamped(data), width, height) {
+        Ok(filled) => {
+            if let Err(err) = context.put_image_data(&filled, 0_f64, 0_f64) {
+                log::warn!("Cant write canvas pixels {:#?}", err);
+            }
+        }
+        Err(err) => log::warn!("Cant create image data {:#?}", err),
+    }
+}
+
+/// Maximum per channel difference still treated as the same colour,
+/// so antialiased stroke edges do not leave a halo around the fill
+const FILL_TOLERANCE: u8 = 48;
+
+/// Scanline flood fill over an RGBA pixel buffer, returns which pixels it filled
+fn fill_region(
+    data: &mut [u8],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    color: [u8; 4],
+) -> Vec<bool> {
+    let mut filled = vec![false; width * height];
+    let pixel = |data: &[u8], x: usize, y: usize| {
+        let i = (y * width + x) * 4;
+        [data[i], data[i + 1], data[i + 2], data[i + 3]]
+    };
+    let target = pixel(data, x, y);
+    // The region is all about the target colour, blending over it once is enough
+    let color = over(color, target);
+    if target == color {
+        return filled;
+    }
+    let matches = |data: &[u8], filled: &[bool], x: usize, y: usize| {
+        !filled[y * width + x]
+            && pixel(data, x, y)
+                .iter()
+                .zip(target.iter())
+                .all(|(a, b)| (*a as i16 - *b as i16).abs() <= FILL_TOLERANCE as i16)
+    };
+
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        if !matches(data, &filled, x, y) {
+            continue;
+        }
+        let mut left = x;
+        while left > 0 && matches(data, &filled, left - 1, y) {
+            left -= 1;
+        }
+        let mut right = x;
+        while right + 1 < width && matches(data, &filled, right + 1, y) {
+            right += 1;
+        }
+        for px in left..=right {
+            let i = (y * width + px) * 4;
+            data[i..i + 4].copy_from_slice(&color);
+            filled[y * width + px] = true;
+            if y > 0 && matches(data, &filled, px, y - 1) {
+                stack.push((px, y - 1));
+            }
+            if y + 1 < height && matches(data, &filled, px, y + 1) {
+                stack.push((px, y + 1));
+            }
+        }
+    }
+    filled
+}
+
+/// `top` painted over `under`, both rgba with straight alpha
+fn over(top: [u8; 4], under: [u8; 4]) -> [u8; 4] {
+    let top_alpha = top[3] as f64 / 255_f64;
+    let under_alpha = under[3] as f64 / 255_f64 * (1_f64 - top_alpha);
+    let alpha = top_alpha + under_alpha;
+    if alpha == 0_f64 {
+        return [0, 0, 0, 0];
+    }
+    let channel = |i: usize| {
+        ((top[i] as f64 * top_alpha + under[i] as f64 * under_alpha) / alpha).round() as u8
+    };
+    [
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha * 255_f64).round() as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    /// A 5x5 white buffer with a black outline around the middle 3x3
+    fn outlined() -> Vec<u8> {
+        let mut data = WHITE.repeat(25);
+        for i in 0..25 {
+            let (x, y) = (i % 5, i / 5);
+            if (1..=3).contains(&x) && (1..=3).contains(&y) && (x != 2 || y != 2) {
+                data[i * 4..i * 4 + 4].copy_from_slice(&BLACK);
+            }
+        }
+        data
+    }
+
+    fn at(data: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let i = (y * 5 + x) * 4;
+        [data[i], data[i + 1], data[i + 2], data[i + 3]]
+    }
+
+    #[test]
+    fn fill_stops_at_the_outline() {
+        let mut data = outlined();
+        let filled = fill_region(&mut data, 5, 5, 2, 2, RED);
+        assert_eq!(filled.iter().filter(|filled| **filled).count(), 1);
+        assert_eq!(at(&data, 2, 2), RED);
+        assert_eq!(at(&data, 1, 1), BLACK);
+        assert_eq!(at(&data, 0, 0), WHITE);
+
+        // Outside the outline the fill goes around it
+        let filled = fill_region(&mut data, 5, 5, 0, 0, RED);
+        assert_eq!(filled.iter().filter(|filled| **filled).count(), 16);
+        assert_eq!(at(&data, 4, 4), RED);
+        assert_eq!(at(&data, 2, 2), RED);
+        assert_eq!(at(&data, 2, 1), BLACK);
+    }
+
+    #[test]
+    fn fill_tolerates_close_colours() {
+        let mut data = WHITE.repeat(3);
+        let edge = 255 - FILL_TOLERANCE;
+        data[4..8].copy_from_slice(&[edge, edge, edge, 255]);
+        data[8..12].copy_from_slice(&[edge - 1, edge, edge, 255]);
+        let filled = fill_region(&mut data, 3, 1, 0, 0, RED);
+        assert_eq!(filled, vec![true, true, false]);
+        assert_eq!(&data[8..12], &[edge - 1, edge, edge, 255]);
+    }
+
+    #[test]
+    fn fill_with_the_seed_colour_does_nothing() {
+        let mut data = outlined();
+        let filled = fill_region(&mut data, 5, 5, 0, 0, WHITE);
+        assert!(filled.iter().all(|filled| !filled));
+        assert_eq!(data, outlined());
+    }
+
+    #[test]
+    fn translucent_fill_blends_over_the_region() {
+        let mut data = outlined();
+        fill_region(&mut data, 5, 5, 0, 0, [255, 0, 0, 128]);
+        assert_eq!(at(&data, 0, 0), [255, 127, 127, 255]);
+        assert_eq!(at(&data, 0, 0), over([255, 0, 0, 128], WHITE));
+
+        assert_eq!(over([255, 0, 0, 128], [0, 0, 0, 0]), [255, 0, 0, 128]);
+        assert_eq!(over([0, 0, 0, 0], WHITE), WHITE);
+        assert_eq!(over([0, 0, 255, 128], [255, 0, 0, 128]), [85, 0, 170, 192]);
+    }
+}
@@ -0,0 +1,234 @@
+use yew::prelude::*;
+use yew::services::interval::{IntervalService, IntervalTask};
+
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::render::{backing_size, context_2d, draw_cached, redraw_cached, StrokeCache};
+use crate::structures::*;
+
+/// Plays a finished drawing back in the time it was drawn
+pub struct Replay {
+    link: ComponentLink<Self>,
+    canvas_ref: NodeRef,
+    context: Option<CanvasRenderingContext2d>,
+    cache: Option<StrokeCache>,
+    /// The part of the drawing reached so far
+    visible: Vec<Stroke>,
+    drawn: (usize, usize),
+    /// Time of the first point, the replay skips the wait before it
+    start: u32,
+    duration: u32,
+    position: u32,
+    playing: bool,
+    speed: f64,
+    _tick: IntervalTask,
+    props: Props,
+}
+
+pub enum Msg {
+    Tick,
+    Play,
+    Pause,
+    Seek(u32),
+    SetSpeed(f64),
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct Props {
+    pub strokes: Vec<Stroke>,
+    pub background: Color,
+}
+
+const TICK_MS: u32 = 40;
+
+const SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 4.0, 8.0];
+
+impl Component for Replay {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_props: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        let times = || {
+            _props
+                .strokes
+                .iter()
+                .flat_map(|stroke| stroke.points.iter().map(|point| point.time))
+        };
+        let start = times().min().unwrap_or(0);
+        let end = times().max().unwrap_or(0);
+        let tick = IntervalService::spawn(
+            std::time::Duration::from_millis(TICK_MS as u64),
+            _link.callback(|_| Msg::Tick),
+        );
+        Self {
+            link: _link,
+            canvas_ref: NodeRef::default(),
+            context: None,
+            cache: None,
+            visible: vec![],
+            drawn: (0, 0),
+            start,
+            duration: end - start,
+            position: 0,
+            playing: true,
+            speed: 2.0,
+            _tick: tick,
+            props: _props,
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        match _msg {
+            Msg::Tick => {
+                if !self.playing {
+                    return false;
+                }
+                let position = self.position + (TICK_MS as f64 * self.speed) as u32;
+                if position >= self.duration {
+                    self.playing = false;
+                }
+                self.seek(position.min(self.duration));
+                true
+            }
+            Msg::Play => {
+                // Playing again from the end starts over
+                if self.position >= self.duration {
+                    self.seek(0);
+                }
+                self.playing = true;
+                true
+            }
+            Msg::Pause => {
+                self.playing = false;
+                true
+            }
+            Msg::Seek(position) => {
+                self.seek(position.min(self.duration));
+                true
+            }
+            Msg::SetSpeed(speed) => {
+                self.speed = speed;
+                true
+            }
+        }
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        if _first_render {
+            let canvas: HtmlCanvasElement =
+                self.canvas_ref.cast().expect("Not html canvas element");
+            let (width, height) = backing_size(&canvas);
+            canvas.set_width(width);
+            canvas.set_height(height);
+            self.context = context_2d(&canvas);
+            self.cache = StrokeCache::new();
+            let position = self.position;
+            self.seek(position);
+        }
+    }
+
+    fn change(&mut self, _props: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        let position = self.position;
+        html! {
+            <div class="box">
+                <canvas ref=self.canvas_ref.clone() style=format!("box-sizing:content-box;border-color:black;border-style:solid;width:100%;aspect-ratio:4/3;background-color:{};", self.props.background)>
+                </canvas>
+                <input type="range" min="0" max=self.duration.to_string() value=position.to_string() style="width:100%;"
+                    oninput=self.link.callback(move |ev: InputData| Msg::Seek(ev.value.parse().unwrap_or(position)))
+                />
+                <div class="level is-mobile">
+                    <div class="level-left">
+                        <div class="level-item">
+                        {
+                            if self.playing {
+                                html! {
+                                    <button class="button" onclick=self.link.callback(|_|Msg::Pause)>{"Pause"}</button>
+                                }
+                            } else {
+                                html! {
+                                    <button class="button" onclick=self.link.callback(|_|Msg::Play)>{"Play"}</button>
+                                }
+                            }
+                        }
+                        </div>
+                        <div class="level-item">
+                            {format!("{:.1}s / {:.1}s", position as f64 / 1000_f64, self.duration as f64 / 1000_f64)}
+                        </div>
+                    </div>
+                    <div class="level-right">
+                        <div class="buttons has-addons">
+                        {
+                            for SPEEDS.iter().map(|speed|{
+                                let speed = *speed;
+                                html!{
+                                    <button
+                                        class=if speed == self.speed {"button is-small is-info is-selected"} else {"button is-small"}
+                                        onclick=self.link.callback(move |_|Msg::SetSpeed(speed))
+                                    >
+                                        {format!("{}x", speed)}
+                                    </button>
+                                }
+                            })
+                        }
+                        </div>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Replay {
+    /// Shows the drawing as it was `position` milliseconds after the first point
+    fn seek(&mut self, position: u32) {
+        let canvas: HtmlCanvasElement = self.canvas_ref.cast().expect("Not html canvas element");
+        let rewound = position < self.position;
+        if rewound {
+            self.visible.clear();
+        }
+        self.position = position;
+
+        let limit = self.start + position;
+        for (index, stroke) in self.props.strokes.iter().enumerate() {
+            let count = stroke
+                .points
+                .iter()
+                .take_while(|point| point.time <= limit)
+                .count();
+            if count == 0 {
+                break;
+            }
+            match self.visible.get_mut(index) {
+                Some(shown) => {
+                    let shown_count = shown.points.len();
+                    shown
+                        .points
+                        .extend_from_slice(&stroke.points[shown_count..count]);
+                }
+                None => self.visible.push(Stroke {
+                    id: stroke.id,
+                    header: stroke.header.clone(),
+                    points: stroke.points[..count].to_vec(),
+                }),
+            }
+        }
+
+        if let Some(context) = &self.context {
+            self.drawn = if rewound {
+                redraw_cached(context, &canvas, self.cache.as_mut(), &self.visible)
+            } else {
+                draw_cached(
+                    context,
+                    &canvas,
+                    self.cache.as_mut(),
+                    &self.visible,
+                    self.drawn,
+                )
+            };
+        }
+    }
+}
@@ -48,6 +48,7 @@ impl Component for Room {
                     SocketMessage::PlayerDisconnected(player) => Msg::PlayerDisconnected(player),
                     SocketMessage::LeaderChange(leader) => Msg::LeaderChange(leader),
                     SocketMessage::GameStart(state) => Msg::GameStarted(state),
+                    SocketMessage::LobbyJoined(lobby) => Msg::Refresh(lobby),
                     _ => {
                         //    log::warn!("Unexpected socket message {:#?}",msg);
                         Msg::Ignore
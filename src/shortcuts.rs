@@ -0,0 +1,155 @@
+//! Keyboard shortcuts of the drawing tools.
+//!
+//! A `Keymap` ties keys to actions, the draw widget takes one as a prop
+//! so embedders can rebind them. Keys are matched on `KeyboardEvent.key`,
+//! letters ignoring case, with Ctrl and Shift having to match exactly.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Brush,
+    Eraser,
+    Fill,
+    Highlighter,
+    Undo,
+    Redo,
+    Smaller,
+    Larger,
+    /// Picks the palette colour at this index
+    Color(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shortcut {
+    pub key: String,
+    /// Also matches Cmd, which Macs use for the same shortcuts
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+impl Shortcut {
+    pub fn key(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub fn ctrl(key: &str) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::key(key)
+        }
+    }
+
+    pub fn ctrl_shift(key: &str) -> Self {
+        Self {
+            shift: true,
+            ..Self::ctrl(key)
+        }
+    }
+
+    fn matches(&self, key: &str, ctrl: bool, shift: bool) -> bool {
+        self.key.to_lowercase() == key.to_lowercase() && self.ctrl == ctrl && self.shift == shift
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.to_uppercase())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap(pub Vec<(Shortcut, Action)>);
+
+impl Default for Keymap {
+    /// B, E, F and H pick a tool, [ and ] change the brush size and the
+    /// number keys pick the first ten palette colours
+    fn default() -> Self {
+        let mut bindings = vec![
+            (Shortcut::key("b"), Action::Brush),
+            (Shortcut::key("e"), Action::Eraser),
+            (Shortcut::key("f"), Action::Fill),
+            (Shortcut::key("h"), Action::Highlighter),
+            (Shortcut::ctrl("z"), Action::Undo),
+            (Shortcut::ctrl_shift("z"), Action::Redo),
+            (Shortcut::key("["), Action::Smaller),
+            (Shortcut::key("]"), Action::Larger),
+        ];
+        for slot in 0..10 {
+            let key = ((slot + 1) % 10).to_string();
+            bindings.push((Shortcut::key(&key), Action::Color(slot)));
+        }
+        Self(bindings)
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, key: &str, ctrl: bool, shift: bool) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(shortcut, _)| shortcut.matches(key, ctrl, shift))
+            .map(|(_, action)| *action)
+    }
+
+    /// Keys bound to `action`, as shown to players
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(shortcut, _)| shortcut.to_string())
+            .collect()
+    }
+
+    /// Tooltip text for a button, with the keys bound to its action if any
+    pub fn hint(&self, label: &str, action: Action) -> String {
+        let keys = self.keys(action);
+        if keys.is_empty() {
+            label.to_string()
+        } else {
+            format!("{} ({})", label, keys.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action("b", false, false), Some(Action::Brush));
+        assert_eq!(keymap.action("E", false, false), Some(Action::Eraser));
+        assert_eq!(keymap.action("z", true, false), Some(Action::Undo));
+        assert_eq!(keymap.action("Z", true, true), Some(Action::Redo));
+        assert_eq!(keymap.action("]", false, false), Some(Action::Larger));
+        assert_eq!(keymap.action("1", false, false), Some(Action::Color(0)));
+        assert_eq!(keymap.action("0", false, false), Some(Action::Color(9)));
+        // Modifiers have to match, so browser shortcuts keep working
+        assert_eq!(keymap.action("z", false, false), None);
+        assert_eq!(keymap.action("b", true, false), None);
+    }
+
+    #[test]
+    fn hints_show_every_binding() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.hint("Redo", Action::Redo), "Redo (Ctrl+Shift+Z)");
+        keymap.0.push((Shortcut::ctrl("y"), Action::Redo));
+        assert_eq!(
+            keymap.hint("Redo", Action::Redo),
+            "Redo (Ctrl+Shift+Z, Ctrl+Y)"
+        );
+        keymap.0.clear();
+        assert_eq!(keymap.hint("Redo", Action::Redo), "Redo");
+    }
+}
@@ -6,8 +6,12 @@ use yew::agent::Agent;
 use yew::agent::Context;
 use yew::agent::HandlerId;
 use yew::prelude::*;
+use yew::services::timeout::{TimeoutService, TimeoutTask};
 use yew::worker::AgentLink;
 
+use crate::latency::LatencyMeter;
+use crate::outbox::Outbox;
+
 use serde::{Deserialize, Serialize};
 use web_sys::*;
 
@@ -16,6 +20,9 @@ use crate::structures::*;
 pub enum AgentInput {
     Connect(String),
     Send(PlayerMessage),
+    /// Joins the last room again after the server closed the session,
+    /// when the player chose to take it over from another tab
+    Resume,
 }
 
 #[derive(Clone, Debug)]
@@ -24,19 +31,73 @@ pub enum AgentOutput {
     SocketConnected,
     SocketDisconnected,
     SocketErrorConnecting,
+    /// The connection dropped during a game, trying again with this attempt
+    SocketReconnecting(u32),
+    /// Back in the room, a fresh `LobbyJoined` follows
+    SocketReconnected,
+    /// Own round trip to the server with the player's id, measured on every pong
+    Latency(String, Latency),
 }
 
 pub struct SocketAgent {
     link: AgentLink<Self>,
     subscribers: Vec<HandlerId>,
     socket: Option<WebSocket>,
+    /// Opened but not connected yet
+    connecting: Option<WebSocket>,
     updatecallback: Callback<(WebSocket, String)>,
+    url: Option<String>,
+    session: Session,
+    reconnect_task: Option<TimeoutTask>,
+    /// Messages sent while the socket was closed
+    outbox: Outbox,
+    latency: LatencyMeter,
+}
+
+/// What the player told the server, sent again after reconnecting
+#[derive(Default, Debug, PartialEq)]
+struct Session {
+    /// Uid and name from `Initialize`
+    player: Option<(String, String)>,
+    /// Room of the last `LobbyJoined`
+    room: Option<String>,
+    /// The server ended the session, only the player can resume it
+    closed: bool,
+    /// Rejoined the room but its state has not arrived yet, until then
+    /// everything sent waits in the outbox
+    rejoining: bool,
+    /// Reconnect attempts since the connection dropped, 0 while connected
+    attempt: u32,
+    /// Word of the turn the player is drawing, in the last state received
+    turn: Option<String>,
 }
 
+impl Session {
+    /// Forgets the room and any reconnect in progress for a new connection,
+    /// the player stays
+    fn start_over(&mut self) {
+        *self = Session {
+            player: self.player.take(),
+            ..Session::default()
+        };
+    }
+}
+
+const RECONNECT_BASE_MS: u32 = 500;
+
+const RECONNECT_MAX_MS: u32 = 30_000;
+
+/// Gives up and reports the disconnect after this many attempts
+const MAX_RECONNECT_ATTEMPTS: u32 = 12;
+
+/// Pongs between reports of the own latency to the server
+const LATENCY_REPORT_EVERY: u32 = 5;
+
 pub enum Msg {
     Connected((WebSocket, String)),
     Disconnected,
     ErrorConnecting,
+    Reconnect,
     SocketMessage(SocketMessage),
 
     // PeerConnect(u32),
@@ -57,7 +118,13 @@ impl Agent for SocketAgent {
             updatecallback: link.callback(|sock| Msg::Connected(sock)),
             link,
             socket: None,
+            connecting: None,
             subscribers: vec![],
+            url: None,
+            session: Session::default(),
+            reconnect_task: None,
+            outbox: Outbox::default(),
+            latency: LatencyMeter::default(),
         }
     }
 
@@ -96,28 +163,94 @@ impl Agent for SocketAgent {
                     .set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
 
                 onmessage_callback.forget();
+                self.connecting = None;
                 self.socket = Some(socket.0);
-                for subs in self.subscribers.iter() {
-                    self.link
-                        .respond(subs.clone(), AgentOutput::SocketConnected)
+                if self.session.attempt > 0 {
+                    self.session.attempt = 0;
+                    self.resume();
+                    self.broadcast(AgentOutput::SocketReconnected);
+                } else {
+                    for subs in self.subscribers.iter() {
+                        self.link
+                            .respond(subs.clone(), AgentOutput::SocketConnected)
+                    }
                 }
             }
             Msg::SocketMessage(msg) => {
                 // log::debug!("socket message {:#?}", msg);
                 // self.handle_socket_msg(&msg);
+                match &msg {
+                    SocketMessage::LobbyJoined(lobby) => {
+                        self.session.room = Some(lobby.id.clone());
+                        self.session.closed = false;
+                        self.session.turn = own_turn(&lobby.state, &self.session.player);
+                        if self.session.rejoining {
+                            self.flush_outbox();
+                        }
+                    }
+                    SocketMessage::GameStart(state)
+                    | SocketMessage::LeaderChange(state)
+                    | SocketMessage::ScoreChange(state)
+                    | SocketMessage::TimeUpdate(state) => {
+                        self.session.turn = own_turn(state, &self.session.player);
+                    }
+                    // Closed on purpose, coming back would only be closed again
+                    SocketMessage::Close(_) => {
+                        self.session.closed = true;
+                        self.session.rejoining = false;
+                        self.outbox.clear();
+                    }
+                    SocketMessage::Pong => self.pong(),
+                    _ => {}
+                }
                 self.broadcast(AgentOutput::SocketMessage(msg));
             }
             Msg::Disconnected => {
                 log::warn!("Disconnected from socket");
                 self.socket = None;
-                self.broadcast(AgentOutput::SocketDisconnected);
+                self.connecting = None;
+                self.session.rejoining = false;
+                self.latency.reset();
+                let resumable =
+                    self.url.is_some() && self.session.room.is_some() && !self.session.closed;
+                if resumable && self.session.attempt < MAX_RECONNECT_ATTEMPTS {
+                    self.session.attempt += 1;
+                    let delay = backoff(self.session.attempt, js_sys::Math::random());
+                    log::info!(
+                        "Reconnecting in {}ms, attempt {}",
+                        delay,
+                        self.session.attempt
+                    );
+                    self.reconnect_task = Some(TimeoutService::spawn(
+                        std::time::Duration::from_millis(delay as u64),
+                        self.link.callback(|_| Msg::Reconnect),
+                    ));
+                    self.broadcast(AgentOutput::SocketReconnecting(self.session.attempt));
+                } else {
+                    self.session.attempt = 0;
+                    self.outbox.clear();
+                    self.broadcast(AgentOutput::SocketDisconnected);
+                }
+            }
+            Msg::Reconnect => {
+                self.reconnect_task = None;
+                // Connect or Resume got there first
+                if self.socket.is_some() || self.connecting.is_some() {
+                    return;
+                }
+                if let Some(url) = self.url.clone() {
+                    self.connect_to_socket(url);
+                }
             }
 
             Msg::SendSocketMessage(data) => {
-                self.send_socket_message(&data);
+                self.send_socket_message(data);
             }
             Msg::ErrorConnecting => {
-                self.broadcast(AgentOutput::SocketErrorConnecting);
+                // A failed reconnect also closes, which schedules the next attempt
+                if self.session.attempt == 0 {
+                    self.broadcast(AgentOutput::SocketErrorConnecting);
+                }
             }
 
             Msg::Ignore => {}
@@ -127,10 +260,25 @@ impl Agent for SocketAgent {
     fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
         match msg {
             AgentInput::Connect(url) => {
+                self.url = Some(url.clone());
+                self.start_over();
                 self.connect_to_socket(url);
             }
             AgentInput::Send(msg) => {
-                self.send_socket_message(&msg);
+                if let PlayerMessage::Initialize(uid, name, _) = &msg {
+                    self.session.player = Some((uid.clone(), name.clone()));
+                }
+                self.send_socket_message(msg);
+            }
+            AgentInput::Resume => {
+                self.session.closed = false;
+                if self.socket.is_some() {
+                    self.resume();
+                } else if let Some(url) = self.url.clone() {
+                    // Counts as a reconnect, so the session is sent once open
+                    self.session.attempt = 1;
+                    self.connect_to_socket(url);
+                }
             }
         }
     }
@@ -175,6 +323,7 @@ impl SocketAgent {
 
                 ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
                 onclose_callback.forget();
+                self.connecting = Some(ws);
             }
             Err(e) => {
                 log::debug!("Cannot connect {:#?}", e);
@@ -183,20 +332,167 @@ impl SocketAgent {
         }
     }
 
-    fn send_socket_message(&self, data: &PlayerMessage) {
+    /// A new session, nothing from the last one is sent and its sockets
+    /// can no longer report back
+    fn start_over(&mut self) {
+        for socket in self.socket.take().into_iter().chain(self.connecting.take()) {
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onerror(None);
+            socket.set_onclose(None);
+            if let Err(er) = socket.close() {
+                log::warn!("Cant close socket {:#?}", er);
+            }
+        }
+        self.reconnect_task = None;
+        self.session.start_over();
+        self.latency.reset();
+        self.outbox.clear();
+    }
+
+    /// Puts the player back in their room on a new connection, the server
+    /// answers `JoinLobby` with the current state of the room. What was
+    /// sent while offline follows once that arrives.
+    fn resume(&mut self) {
+        if let Some((uid, name)) = self.session.player.clone() {
+            self.send_socket_message(PlayerMessage::Initialize(uid, name, PROTOCOL_VERSION));
+        }
+        if let Some(room) = self.session.room.clone() {
+            self.send_socket_message(PlayerMessage::JoinLobby(room));
+            self.session.rejoining = true;
+        }
+    }
+
+    /// Sends what was queued while offline, in the order it was sent.
+    /// Strokes and controls only still belong to the drawing if the player
+    /// is still drawing the same turn, it may have passed on during the outage.
+    fn flush_outbox(&mut self) {
+        self.session.rejoining = false;
+        self.outbox.keep_drawing_of(self.session.turn.as_deref());
+        let queued: Vec<_> = self.outbox.drain().collect();
+        for message in queued {
+            self.send_socket_message(message);
+        }
+    }
+
+    fn pong(&mut self) {
+        let latency = match self.latency.pong_received(js_sys::Date::now()) {
+            Some(latency) => latency,
+            None => return,
+        };
+        if let Some((uid, _)) = &self.session.player {
+            self.broadcast(AgentOutput::Latency(uid.clone(), latency));
+        }
+        if self.latency.samples.is_multiple_of(LATENCY_REPORT_EVERY) {
+            self.send_socket_message(PlayerMessage::Latency(latency));
+        }
+    }
+
+    fn send_socket_message(&mut self, data: PlayerMessage) {
         // log::debug!("Send Message {:#?}",data);
         match &self.socket {
+            Some(_) if self.session.rejoining => {
+                self.outbox.push(data, self.session.turn.as_deref())
+            }
             Some(socket) => match bincode::serialize(&data) {
                 Ok(bytes) => {
                     if let Err(er) = socket.send_with_u8_array(&bytes[..]) {
                         log::warn!("Cant send message {:#?}", er);
+                    } else if let PlayerMessage::Ping = data {
+                        self.latency.ping_sent(js_sys::Date::now());
                     }
                 }
                 Err(er) => {
                     log::error!("Cant serialize to bincode data {:#?}", data);
                 }
             },
-            None => log::error!("Trying to send data without connection {:#?}", data),
+            None => self.outbox.push(data, self.session.turn.as_deref()),
         }
     }
 }
+
+/// Delay before reconnect `attempt`, counting from 1. It doubles up to
+/// `RECONNECT_MAX_MS` and `random` takes up to half of it off, so clients
+/// dropped together by a server restart do not all come back at once.
+fn backoff(attempt: u32, random: f64) -> u32 {
+    let ceiling = RECONNECT_BASE_MS
+        .saturating_mul(1 << (attempt.max(1) - 1).min(16))
+        .min(RECONNECT_MAX_MS);
+    (ceiling as f64 * (1_f64 - random / 2_f64)) as u32
+}
+
+/// Word of the turn in `state` if `player` is drawing it and has chosen it
+fn own_turn(state: &State, player: &Option<(String, String)>) -> Option<String> {
+    match (state, player) {
+        (State::Game(leader, _, data), Some((uid, _))) if leader == uid => match &data.word {
+            WordState::Word(word) => Some(word.clone()),
+            WordState::ChoseWords(_) => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1, 0_f64), 500);
+        assert_eq!(backoff(2, 0_f64), 1000);
+        assert_eq!(backoff(4, 0_f64), 4000);
+        assert_eq!(backoff(7, 0_f64), 30_000);
+        assert_eq!(backoff(40, 0_f64), 30_000);
+        // Jitter takes off at most half
+        assert_eq!(backoff(3, 1_f64), 1000);
+        assert!((1000..=2000).contains(&backoff(3, 0.3)));
+    }
+
+    #[test]
+    fn own_turn_needs_the_player_drawing_a_chosen_word() {
+        let player = Some(("uid".to_string(), "name".to_string()));
+        let game = |leader: &str, word| {
+            State::Game(
+                leader.to_string(),
+                Scores::default(),
+                GameData {
+                    word,
+                    ..GameData::default()
+                },
+            )
+        };
+        let cat = || WordState::Word("cat".to_string());
+        assert_eq!(
+            own_turn(&game("uid", cat()), &player),
+            Some("cat".to_string())
+        );
+        assert_eq!(own_turn(&game("other", cat()), &player), None);
+        assert_eq!(
+            own_turn(&game("uid", WordState::ChoseWords(vec![])), &player),
+            None
+        );
+        assert_eq!(own_turn(&State::Lobby("uid".to_string()), &player), None);
+        assert_eq!(own_turn(&game("uid", cat()), &None), None);
+    }
+
+    #[test]
+    fn starting_over_keeps_only_the_player() {
+        let player = Some(("uid".to_string(), "name".to_string()));
+        let mut session = Session {
+            player: player.clone(),
+            room: Some("room".to_string()),
+            closed: true,
+            rejoining: true,
+            attempt: 7,
+            turn: Some("cat".to_string()),
+        };
+        session.start_over();
+        assert_eq!(
+            session,
+            Session {
+                player,
+                ..Session::default()
+            }
+        );
+    }
+}
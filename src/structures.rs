@@ -24,7 +24,10 @@ pub enum State {
 use std::collections::HashSet;
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct GameData {
-    pub drawing: Vec<Point>,
+    #[serde(with = "crate::codec")]
+    pub drawing: Vec<Stroke>,
+    /// Set by the drawer at most once a turn, the board is white until then
+    pub background: Option<Color>,
     pub guessed: HashSet<String>,
     pub time: u32,
     pub word: WordState,
@@ -47,36 +50,366 @@ impl Default for WordState {
 }
 
 use web_sys::*;
+
+/// Logical size of the board, points and line widths are in these units
+pub const BOARD_WIDTH: f64 = 1600_f64;
+pub const BOARD_HEIGHT: f64 = 1200_f64;
+
+/// The board scaled to fit a surface and centred on it, so every client
+/// shows the same part of the drawing whatever its shape
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Letterbox {
+    pub scale: f64,
+    pub left: f64,
+    pub top: f64,
+}
+
+impl Letterbox {
+    pub fn fit(width: f64, height: f64) -> Self {
+        let scale = (width / BOARD_WIDTH).min(height / BOARD_HEIGHT);
+        Self {
+            scale,
+            left: (width - BOARD_WIDTH * scale) / 2_f64,
+            top: (height - BOARD_HEIGHT * scale) / 2_f64,
+        }
+    }
+
+    /// Fits the canvas pixels, which includes the device pixel ratio
+    pub fn of_canvas(canvas: &HtmlCanvasElement) -> Self {
+        Self::fit(canvas.width() as f64, canvas.height() as f64)
+    }
+
+    /// Board position of a position on the surface
+    pub fn to_board(self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.left) / self.scale, (y - self.top) / self.scale)
+    }
+}
+
+impl Point {
+    pub fn get_x(&self, board: &Letterbox) -> f64 {
+        board.left + self.x * board.scale
+    }
+    pub fn get_y(&self, board: &Letterbox) -> f64 {
+        board.top + self.y * board.scale
+    }
+}
+
 impl Point {
-    pub fn get_x(&self, canvas: &HtmlCanvasElement) -> f64 {
-        self.x * self.get_scale_factor(canvas)
+    pub fn midpoint(&self, other: &Point) -> Point {
+        Point {
+            x: (self.x + other.x) / 2_f64,
+            y: (self.y + other.y) / 2_f64,
+            pressure: (self.pressure + other.pressure) / 2_f64,
+            time: (self.time + other.time) / 2,
+        }
+    }
+}
+
+/// Quadratic curve from `start` to `end` bending towards `control`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CurvePiece {
+    pub start: Point,
+    pub control: Point,
+    pub end: Point,
+}
+
+impl Stroke {
+    /// Smooths a freehand stroke into curves through the midpoints between
+    /// samples, with the samples as control points. Piece `k` only depends on
+    /// points `k - 1..=k + 1`, so it is final once point `k + 1` exists and the
+    /// result is the same however the points were batched. Pieces before the
+    /// one ending after point `from` are skipped as already drawn.
+    ///
+    /// The curve stops halfway to the last point, finished strokes repeat
+    /// their last point so the final piece reaches it.
+    pub fn curve_pieces(&self, from: usize) -> Vec<CurvePiece> {
+        let points = &self.points;
+        (from.saturating_sub(1)..points.len().saturating_sub(1))
+            .map(|k| CurvePiece {
+                start: if k == 0 {
+                    points[0]
+                } else {
+                    points[k - 1].midpoint(&points[k])
+                },
+                control: points[k],
+                end: points[k].midpoint(&points[k + 1]),
+            })
+            .collect()
+    }
+
+    /// Adds strokes received from the drawer, a stroke with the id of the
+    /// last one continues it, anything else starts a new stroke
+    pub fn merge(drawing: &mut Vec<Stroke>, strokes: Vec<Stroke>) {
+        for mut stroke in strokes {
+            match drawing.last_mut() {
+                Some(last) if last.id == stroke.id => last.points.append(&mut stroke.points),
+                _ => drawing.push(stroke),
+            }
+        }
+    }
+}
+
+/// Changes to the drawing that are not new points, sent by the drawer
+/// and applied in the same order by viewers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DrawControl {
+    Clear,
+    Undo(u32),
+    Redo(#[serde(with = "crate::codec::single")] Stroke),
+    /// Colour under the strokes, erasing shows it again
+    Background(Color),
+}
+
+impl DrawControl {
+    pub fn apply(&self, drawing: &mut Vec<Stroke>, background: &mut Option<Color>) {
+        match self {
+            DrawControl::Clear => drawing.clear(),
+            DrawControl::Undo(id) => drawing.retain(|stroke| &stroke.id != id),
+            DrawControl::Redo(stroke) => drawing.push(stroke.clone()),
+            DrawControl::Background(color) => *background = Some(*color),
+        }
     }
-    pub fn get_y(&self, canvas: &HtmlCanvasElement) -> f64 {
-        self.y * self.get_scale_factor(canvas)
+}
+
+/// Colours offered by the drawing toolbox, the wire format refers to
+/// them by index so only ever append to this list
+pub const PALETTE: [Color; 16] = [
+    Color::BLACK,
+    Color::rgb(0xff, 0x00, 0x00),
+    Color::rgb(0x00, 0x80, 0x00),
+    Color::rgb(0x00, 0x00, 0xff),
+    Color::rgb(0xff, 0xff, 0x00),
+    Color::WHITE,
+    Color::rgb(0xff, 0xa5, 0x00),
+    Color::rgb(0x80, 0x00, 0x80),
+    Color::rgb(0x8b, 0x45, 0x13),
+    Color::rgb(0x80, 0x80, 0x80),
+    Color::rgb(0xc0, 0xc0, 0xc0),
+    Color::rgb(0xff, 0x69, 0xb4),
+    Color::rgb(0x00, 0xff, 0xff),
+    Color::rgb(0x00, 0xff, 0x00),
+    Color::rgb(0x00, 0x00, 0x80),
+    Color::rgb(0xf1, 0xc2, 0x7d),
+];
+
+/// An opaque colour, sent as `#rrggbb` and rejected when it is anything else
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorError(pub String);
+
+impl std::fmt::Display for ColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Not a #rgb or #rrggbb colour: {:?}", self.0)
+    }
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(0xff, 0xff, 0xff);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Hue in degrees, saturation and value from 0 to 1
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Self {
+        let hue = hue.rem_euclid(360_f64) / 60_f64;
+        let chroma = value * saturation;
+        let x = chroma * (1_f64 - (hue % 2_f64 - 1_f64).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0_f64),
+            1 => (x, chroma, 0_f64),
+            2 => (0_f64, chroma, x),
+            3 => (0_f64, x, chroma),
+            4 => (x, 0_f64, chroma),
+            _ => (chroma, 0_f64, x),
+        };
+        let channel = |c: f64| ((c + value - chroma) * 255_f64).round() as u8;
+        Self::rgb(channel(r), channel(g), channel(b))
     }
 
-    pub fn get_scale_factor(&self, canvas: &HtmlCanvasElement) -> f64 {
-        let wf = canvas.width() as f64 / self.width;
-        let hf = canvas.height() as f64 / self.height;
-        if wf < hf {
-            wf
+    pub fn to_hsv(self) -> (f64, f64, f64) {
+        let (r, g, b) = (
+            self.r as f64 / 255_f64,
+            self.g as f64 / 255_f64,
+            self.b as f64 / 255_f64,
+        );
+        let max = r.max(g).max(b);
+        let chroma = max - r.min(g).min(b);
+        let hue = if chroma == 0_f64 {
+            0_f64
+        } else if max == r {
+            60_f64 * ((g - b) / chroma).rem_euclid(6_f64)
+        } else if max == g {
+            60_f64 * ((b - r) / chroma + 2_f64)
         } else {
-            hf
+            60_f64 * ((r - g) / chroma + 4_f64)
+        };
+        let saturation = if max == 0_f64 { 0_f64 } else { chroma / max };
+        (hue, saturation, max)
+    }
+
+    pub fn rgba(self) -> [u8; 4] {
+        [self.r, self.g, self.b, 255]
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = ColorError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || ColorError(text.to_string());
+        let hex = text.strip_prefix('#').ok_or_else(error)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).unwrap();
+        let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        match hex.len() {
+            3 => Ok(Self::rgb(digit(0) * 17, digit(1) * 17, digit(2) * 17)),
+            6 => Ok(Self::rgb(pair(0), pair(2), pair(4))),
+            _ => Err(error()),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Point {
-    pub id: u32,
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl std::convert::TryFrom<String> for Color {
+    type Error = ColorError;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_string()
+    }
+}
+
+/// Everything that stays the same for the whole stroke
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StrokeHeader {
+    pub tool: Tool,
+    pub color: Color,
+    /// In board units
     pub line_width: u32,
+    /// 255 is opaque
+    pub opacity: u8,
+}
+
+impl StrokeHeader {
+    pub fn alpha(&self) -> f64 {
+        self.opacity as f64 / 255_f64
+    }
+
+    /// Translucent strokes are drawn as one path at once, stroking over
+    /// their own end would make the overlap darker
+    pub fn is_translucent(&self) -> bool {
+        self.opacity < u8::MAX || self.tool == Tool::Highlighter
+    }
+
+    /// Line width in board units at a point. Translucent strokes ignore
+    /// pressure, pieces of different width would overlap and darken where
+    /// they meet.
+    pub fn width_at(&self, pressure: f64) -> f64 {
+        if self.is_translucent() {
+            self.line_width as f64
+        } else {
+            self.line_width as f64 * pressure
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Point {
     pub x: f64,
     pub y: f64,
-    pub width: f64,
-    pub height: f64,
-    pub draw: bool,
-    pub color: String,
-    pub eraser: bool,
+    /// Multiplies the stroke's line width, from pen pressure and tilt
+    pub pressure: f64,
+    /// Milliseconds into the turn when the point was drawn, for replays
+    pub time: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub id: u32,
+    pub header: StrokeHeader,
+    pub points: Vec<Point>,
+}
+
+#[cfg(test)]
+impl Stroke {
+    /// An opaque stroke 4 units wide through `points` given as
+    /// (x, y, pressure), one 16ms frame apart
+    pub fn fixture(id: u32, tool: Tool, color: Color, points: &[(f64, f64, f64)]) -> Self {
+        Stroke {
+            id,
+            header: StrokeHeader {
+                tool,
+                color,
+                line_width: 4,
+                opacity: u8::MAX,
+            },
+            points: points
+                .iter()
+                .enumerate()
+                .map(|(i, &(x, y, pressure))| Point {
+                    x,
+                    y,
+                    pressure,
+                    time: i as u32 * 16,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Tool {
+    Brush,
+    Eraser,
+    Fill,
+    Line,
+    Rectangle,
+    Ellipse,
+    /// Multiplies its colour over the drawing at an even width
+    Highlighter,
+}
+
+impl Tool {
+    /// Shapes are sent as two points, the start and end corner of the drag
+    pub fn is_shape(&self) -> bool {
+        match self {
+            Tool::Line | Tool::Rectangle | Tool::Ellipse => true,
+            Tool::Brush | Tool::Eraser | Tool::Fill | Tool::Highlighter => false,
+        }
+    }
+}
+
+/// A finished turn, kept by the client for the end of game gallery
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurnRecord {
+    pub turn: u32,
+    pub drawer: String,
+    pub word: String,
+    /// Names of the players who guessed the word
+    pub guessed: Vec<String>,
+    pub drawing: Vec<Stroke>,
+    pub background: Color,
 }
 
 impl State {
@@ -101,16 +434,29 @@ pub enum PlayerStatus {
     JoinedLobby(String),
 }
 
-#[derive(Debug, Deserialize, Copy, Clone)]
+/// Why the server ended the session, sent just before it closes the socket
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
 pub enum CloseCodes {
     WrongInit,
     CantCreateLobby,
     CantLoinLobbyDoestExist,
     NewSessionOpened,
+    /// The server speaks this other protocol version
+    IncompatibleVersion(u32),
+    /// A code added to the server after this client was built
+    #[serde(other)]
+    Unknown,
 }
 impl std::fmt::Display for CloseCodes {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            CloseCodes::WrongInit => write!(f, "The server did not accept this player"),
+            CloseCodes::CantCreateLobby => write!(f, "The server could not create a room"),
+            CloseCodes::CantLoinLobbyDoestExist => write!(f, "This room does not exist"),
+            CloseCodes::NewSessionOpened => write!(f, "The game was opened in another tab"),
+            CloseCodes::IncompatibleVersion(_) => write!(f, "The game was updated"),
+            CloseCodes::Unknown => write!(f, "The server closed the connection"),
+        }
     }
 }
 
@@ -120,9 +466,15 @@ pub struct AudioChunk {
     pub type_: String,
 }
 
+/// Messages are positional bincode enums, so any change to their order or
+/// fields on either side has to bump this. The server closes sessions of
+/// another version with `CloseCodes::IncompatibleVersion`.
+pub const PROTOCOL_VERSION: u32 = 3;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum PlayerMessage {
-    Initialize(String, String),
+    /// Uid, name and `PROTOCOL_VERSION`
+    Initialize(String, String, u32),
     JoinLobby(String),
     WordChosen(String),
     CreateLobby,
@@ -133,7 +485,10 @@ pub enum PlayerMessage {
     AudioChat(AudioChunk),
     StartGame,
 
-    AddPoints(Vec<Point>),
+    AddPoints(#[serde(with = "crate::codec")] Vec<Stroke>),
+    DrawControl(DrawControl),
+    /// Own round trip to the server, for it to pass on to the others
+    Latency(Latency),
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -151,7 +506,125 @@ pub enum SocketMessage {
 
     GameStart(State),
 
-    AddPoints(Vec<Point>),
+    AddPoints(#[serde(with = "crate::codec")] Vec<Stroke>),
 
     Pong,
+
+    DrawControl(DrawControl),
+    /// Round trip another player reported
+    PlayerLatency(String, Latency),
+}
+
+/// Round trip time to the server and how much it varies, in milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Latency {
+    pub rtt: u32,
+    pub jitter: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quality {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl Latency {
+    /// Drawing keeps up on good connections, on poor ones strokes arrive
+    /// noticeably late or in bursts
+    pub fn quality(&self) -> Quality {
+        if self.rtt > 400 || self.jitter > 100 {
+            Quality::Poor
+        } else if self.rtt > 150 || self.jitter > 30 {
+            Quality::Fair
+        } else {
+            Quality::Good
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_parse_only_hex() {
+        assert_eq!("#12AB34".parse(), Ok(Color::rgb(0x12, 0xab, 0x34)));
+        assert_eq!("#fff".parse(), Ok(Color::WHITE));
+        for text in &[
+            "red", "#12345", "#12345g", "12ab34", "#1234567", "url(x)", "",
+        ] {
+            assert!(text.parse::<Color>().is_err(), "{}", text);
+        }
+        assert_eq!(Color::rgb(0x12, 0xab, 0x34).to_string(), "#12ab34");
+    }
+
+    #[test]
+    fn malformed_colors_are_rejected_from_the_wire() {
+        let header = Stroke::fixture(0, Tool::Brush, Color::rgb(0, 0xff, 0), &[]).header;
+        let bytes = bincode::serialize(&header).unwrap();
+        assert_eq!(
+            bincode::deserialize::<StrokeHeader>(&bytes).unwrap(),
+            header
+        );
+
+        // "#00ff00" becomes "x00ff00", which a canvas would silently ignore
+        let bad: Vec<u8> = bytes
+            .iter()
+            .map(|&byte| if byte == b'#' { b'x' } else { byte })
+            .collect();
+        assert!(bincode::deserialize::<StrokeHeader>(&bad).is_err());
+    }
+
+    #[test]
+    fn hsv_round_trips() {
+        for color in PALETTE.iter() {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(&Color::from_hsv(h, s, v), color);
+        }
+        assert_eq!(Color::from_hsv(120_f64, 1_f64, 1_f64), PALETTE[13]);
+    }
+
+    #[test]
+    fn clearing_keeps_the_background() {
+        let mut drawing = vec![Stroke::fixture(3, Tool::Brush, Color::BLACK, &[])];
+        let mut background = None;
+        DrawControl::Background(PALETTE[4]).apply(&mut drawing, &mut background);
+        DrawControl::Clear.apply(&mut drawing, &mut background);
+        assert!(drawing.is_empty());
+        assert_eq!(background, Some(PALETTE[4]));
+    }
+
+    #[test]
+    fn handshake_layout_is_stable() {
+        // Whatever else changes, an old server has to be able to read the
+        // version and an old client has to be able to read the refusal
+        let init = PlayerMessage::Initialize("u".to_string(), "n".to_string(), PROTOCOL_VERSION);
+        let mut expected = vec![0, 0, 0, 0];
+        for text in &["u", "n"] {
+            expected.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+            expected.extend_from_slice(text.as_bytes());
+        }
+        expected.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        assert_eq!(bincode::serialize(&init).unwrap(), expected);
+
+        let close = [3, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0];
+        match bincode::deserialize(&close).unwrap() {
+            SocketMessage::Close(CloseCodes::IncompatibleVersion(7)) => {}
+            other => panic!("expected a version refusal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_close_codes_still_close() {
+        let close = |code: u8| [3, 0, 0, 0, code, 0, 0, 0];
+        match bincode::deserialize(&close(3)).unwrap() {
+            SocketMessage::Close(code) => assert_eq!(code, CloseCodes::NewSessionOpened),
+            other => panic!("expected a close, got {:?}", other),
+        }
+        match bincode::deserialize(&close(42)).unwrap() {
+            SocketMessage::Close(code) => assert_eq!(code, CloseCodes::Unknown),
+            other => panic!("expected a close, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,296 @@
+//! Vector export of a drawing.
+//!
+//! Draws the same curves and shapes as the canvas as a standalone SVG
+//! document, without needing a browser. Eraser strokes mask whatever was
+//! drawn before them, so later strokes on top stay visible. Fills are a
+//! flood over canvas pixels that has no vector equivalent and are left
+//! out, `omissions` tells the export UI so it can say so.
+
+use std::fmt::Write;
+
+use crate::structures::*;
+
+/// Renders `strokes` on a `width` by `height` document filled with
+/// `background`, the board letterboxed the same way as on a canvas
+pub fn render(strokes: &[Stroke], background: Color, width: f64, height: f64) -> String {
+    let mut defs = String::new();
+    let mut body = String::new();
+    let board = Letterbox::fit(width, height);
+    for (index, stroke) in strokes.iter().enumerate() {
+        let header = &stroke.header;
+        match header.tool {
+            Tool::Brush | Tool::Highlighter => body.push_str(&curves(stroke, &board, header.color)),
+            Tool::Eraser => {
+                let _ = write!(
+                    defs,
+                    r#"<mask id="erase{}" maskUnits="userSpaceOnUse"><rect width="{}" height="{}" fill="white"/>{}</mask>"#,
+                    index,
+                    num(width),
+                    num(height),
+                    curves(stroke, &board, Color::BLACK)
+                );
+                body = format!(r#"<g mask="url(#erase{})">{}</g>"#, index, body);
+            }
+            Tool::Line | Tool::Rectangle | Tool::Ellipse => {
+                if let [start, end, ..] = stroke.points[..] {
+                    body.push_str(&shape(header, &board, &start, &end));
+                }
+            }
+            Tool::Fill => {}
+        }
+    }
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" "#,
+            r#"fill="none" stroke-linecap="round" stroke-linejoin="round">"#,
+            r#"<rect width="{w}" height="{h}" fill="{background}"/><defs>{defs}</defs>"#,
+            // Highlighters multiply with the strokes under them but not with the
+            // background, which the canvas shows from CSS under its stroke layer
+            r#"<g style="isolation:isolate">{body}</g></svg>"#
+        ),
+        w = num(width),
+        h = num(height),
+        background = background,
+        defs = defs,
+        body = body
+    )
+}
+
+/// What the SVG of `strokes` leaves out compared to the canvas, if anything
+pub fn omissions(strokes: &[Stroke]) -> Option<&'static str> {
+    if strokes
+        .iter()
+        .any(|stroke| stroke.header.tool == Tool::Fill)
+    {
+        Some("Bucket fills are left out of SVG")
+    } else {
+        None
+    }
+}
+
+/// The smoothed pieces of a freehand stroke, one path per run of equal width
+fn curves(stroke: &Stroke, board: &Letterbox, color: Color) -> String {
+    let mut out = String::new();
+    let mut width = None;
+    for piece in stroke.curve_pieces(0) {
+        let piece_width = stroke.header.width_at(piece.control.pressure) * board.scale;
+        if width != Some(piece_width) {
+            if width.is_some() {
+                out.push_str(r#""/>"#);
+            }
+            let _ = write!(
+                out,
+                r#"<path stroke="{}"{} stroke-width="{}" d="M{} {}"#,
+                color,
+                translucency(&stroke.header),
+                num(piece_width),
+                num(piece.start.get_x(board)),
+                num(piece.start.get_y(board))
+            );
+            width = Some(piece_width);
+        }
+        let _ = write!(
+            out,
+            " Q{} {} {} {}",
+            num(piece.control.get_x(board)),
+            num(piece.control.get_y(board)),
+            num(piece.end.get_x(board)),
+            num(piece.end.get_y(board))
+        );
+    }
+    if width.is_some() {
+        out.push_str(r#""/>"#);
+    }
+    out
+}
+
+fn shape(header: &StrokeHeader, board: &Letterbox, start: &Point, end: &Point) -> String {
+    let (x0, y0) = (start.get_x(board), start.get_y(board));
+    let (x1, y1) = (end.get_x(board), end.get_y(board));
+    let paint = format!(
+        r#"stroke="{}"{} stroke-width="{}""#,
+        header.color,
+        translucency(header),
+        num(header.line_width as f64 * board.scale)
+    );
+    match header.tool {
+        Tool::Line => format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+            num(x0),
+            num(y0),
+            num(x1),
+            num(y1),
+            paint
+        ),
+        Tool::Rectangle => format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            num(x0.min(x1)),
+            num(y0.min(y1)),
+            num((x1 - x0).abs()),
+            num((y1 - y0).abs()),
+            paint
+        ),
+        Tool::Ellipse => format!(
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}/>"#,
+            num((x0 + x1) / 2_f64),
+            num((y0 + y1) / 2_f64),
+            num((x1 - x0).abs() / 2_f64),
+            num((y1 - y0).abs() / 2_f64),
+            paint
+        ),
+        Tool::Brush | Tool::Eraser | Tool::Fill | Tool::Highlighter => String::new(),
+    }
+}
+
+/// Attributes for strokes that let the drawing show through
+fn translucency(header: &StrokeHeader) -> String {
+    let mut out = String::new();
+    if header.opacity < u8::MAX {
+        let _ = write!(out, r#" stroke-opacity="{}""#, num(header.alpha()));
+    }
+    if header.tool == Tool::Highlighter {
+        out.push_str(r#" style="mix-blend-mode:multiply""#);
+    }
+    out
+}
+
+/// Two decimals at most, so output does not depend on float noise
+fn num(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_drawing() {
+        let strokes = vec![
+            Stroke::fixture(
+                0,
+                Tool::Brush,
+                PALETTE[1],
+                &[
+                    (10.0, 10.0, 1.0),
+                    (20.0, 30.0, 1.0),
+                    (40.0, 30.0, 0.5),
+                    (40.0, 30.0, 0.5),
+                ],
+            ),
+            Stroke::fixture(
+                0,
+                Tool::Rectangle,
+                Color::BLACK,
+                &[(50.0, 60.0, 1.0), (30.0, 20.0, 1.0)],
+            ),
+            Stroke::fixture(
+                0,
+                Tool::Eraser,
+                Color::BLACK,
+                &[(0.0, 0.0, 1.0), (0.0, 0.0, 1.0)],
+            ),
+            Stroke::fixture(0, Tool::Fill, PALETTE[3], &[(100.0, 50.0, 1.0)]),
+            Stroke::fixture(
+                0,
+                Tool::Ellipse,
+                PALETTE[2],
+                &[(0.0, 0.0, 1.0), (20.0, 10.0, 1.0)],
+            ),
+        ];
+        assert_eq!(
+            render(
+                &strokes,
+                PALETTE[4],
+                BOARD_WIDTH * 2_f64,
+                BOARD_HEIGHT * 2_f64
+            ),
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="3200" height="2400" viewBox="0 0 3200 2400" "#,
+                r#"fill="none" stroke-linecap="round" stroke-linejoin="round">"#,
+                r##"<rect width="3200" height="2400" fill="#ffff00"/>"##,
+                r#"<defs><mask id="erase2" maskUnits="userSpaceOnUse"><rect width="3200" height="2400" fill="white"/>"#,
+                r##"<path stroke="#000000" stroke-width="8" d="M0 0 Q0 0 0 0"/></mask></defs>"##,
+                r#"<g style="isolation:isolate"><g mask="url(#erase2)">"#,
+                r##"<path stroke="#ff0000" stroke-width="8" d="M20 20 Q20 20 30 40 Q40 60 60 60"/>"##,
+                r##"<path stroke="#ff0000" stroke-width="4" d="M60 60 Q80 60 80 60"/>"##,
+                r##"<rect x="60" y="40" width="40" height="80" stroke="#000000" stroke-width="8"/>"##,
+                "</g>",
+                r##"<ellipse cx="20" cy="10" rx="20" ry="10" stroke="#008000" stroke-width="8"/>"##,
+                "</g></svg>"
+            )
+        );
+    }
+
+    #[test]
+    fn translucent_strokes_are_one_path() {
+        let mut highlighter = Stroke::fixture(
+            0,
+            Tool::Highlighter,
+            PALETTE[4],
+            &[
+                (10.0, 10.0, 1.0),
+                (20.0, 30.0, 0.5),
+                (40.0, 30.0, 0.2),
+                (40.0, 30.0, 0.2),
+            ],
+        );
+        highlighter.header.opacity = 51;
+        let mut brush = highlighter.clone();
+        brush.header.tool = Tool::Brush;
+        brush.header.opacity = 128;
+        let svg = render(
+            &[highlighter, brush],
+            Color::WHITE,
+            BOARD_WIDTH * 2_f64,
+            BOARD_HEIGHT * 2_f64,
+        );
+        assert!(svg.contains(concat!(
+            r##"<path stroke="#ffff00" stroke-opacity="0.2" style="mix-blend-mode:multiply" "##,
+            r#"stroke-width="8" d="M20 20 Q20 20 30 40 Q40 60 60 60 Q80 60 80 60"/>"#
+        )));
+        // Pen pressure does not split a translucent brush into overlapping paths
+        assert!(svg.contains(concat!(
+            r##"<path stroke="#ffff00" stroke-opacity="0.5" "##,
+            r#"stroke-width="8" d="M20 20 Q20 20 30 40 Q40 60 60 60 Q80 60 80 60"/>"#
+        )));
+    }
+
+    #[test]
+    fn erasers_mask_by_their_opacity() {
+        let mut eraser = Stroke::fixture(
+            0,
+            Tool::Eraser,
+            Color::BLACK,
+            &[(0.0, 0.0, 1.0), (10.0, 0.0, 1.0)],
+        );
+        eraser.header.opacity = 51;
+        let fill = Stroke::fixture(0, Tool::Fill, PALETTE[3], &[(100.0, 50.0, 1.0)]);
+        let strokes = vec![fill, eraser];
+        let svg = render(&strokes, Color::WHITE, BOARD_WIDTH, BOARD_HEIGHT);
+        assert!(svg.contains(r##"<path stroke="#000000" stroke-opacity="0.2" stroke-width="4""##));
+        assert!(omissions(&strokes).is_some());
+        assert!(omissions(&strokes[1..]).is_none());
+    }
+
+    #[test]
+    fn letterboxes_the_board() {
+        let strokes = vec![Stroke::fixture(
+            0,
+            Tool::Line,
+            Color::BLACK,
+            &[(0.0, 0.0, 1.0), (200.0, 100.0, 1.0)],
+        )];
+        // Twice as wide as the board, it is centred with the height fitting
+        assert!(render(&strokes, Color::WHITE, 3200_f64, 1200_f64)
+            .contains(r#"x1="800" y1="0" x2="1000" y2="100""#));
+        assert_eq!(num(-0.001), "0");
+        assert_eq!(num(1.001), "1");
+        assert_eq!(num(2.5), "2.5");
+    }
+}